@@ -9,9 +9,28 @@ pub fn get() -> clap::App<'static, 'static> {
         (author: "Max Beck <rytonemail@gmail.com>")
 
         (@arg PROJECT: "Project file to open")
+        (@arg LIVE: -l --live "Show audio from the input device instead of the project's audio files")
 
         (@subcommand configure_audio =>
-            (about: "Select audio host and output")
+            (about: "Select audio host and output, prompts on stdin unless a selection is given")
+            (@arg INPUT: -i --input "Select the input device for live mode instead")
+            (@arg LIST: -l --list "List hosts, devices and their supported formats")
+            (@arg FORMAT: --format +takes_value possible_values(&["text", "json"]) default_value("text") "Output format of --list")
+            (@arg HOST: --host +takes_value "Audio host to use, resets the device")
+            (@arg DEVICE: --device +takes_value "Device to use, or \"default\"")
+            (@arg BUFFER_MS: --("buffer-ms") +takes_value conflicts_with[INPUT] "Output buffer size in milliseconds")
+            (@arg SAMPLE_RATE: --("sample-rate") +takes_value conflicts_with[INPUT] "Output sample rate, or \"default\"")
+        )
+
+        (@subcommand new =>
+            (about: "Create a project from a directory of audio files")
+            (@arg DIR: +required "Directory to scan for audio files")
+            (@arg OUTPUT: -o --output +takes_value "Project file to write [default: <DIR>/project.rprj]")
+            (@arg COLUMNS: -c --columns +takes_value "Number of grid columns [default: as square as possible]")
+            (@arg SORT: -s --sort +takes_value possible_values(&["name", "modified"]) default_value("name") "Scope order")
+            (@arg REVERSE: -r --reverse "Reverse scope order")
+            (@arg CENTERING: --centering +takes_value possible_values(&["none", "zero-crossing", "fundamental-phase"]) default_value("zero-crossing") "Centering algorithm for all scopes")
+            (@arg MASTER: -m --master +takes_value "Audio file to use as master [default: a file named like \"master\", or all files]")
         )
     )
 }
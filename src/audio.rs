@@ -1,4 +1,9 @@
+pub mod capture;
 pub mod connection;
+pub mod envelope;
+pub mod event_loop;
+pub mod filter;
 pub mod mixer;
+pub mod overview;
 pub mod playback;
 pub mod source;
@@ -0,0 +1,358 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::{thread, time};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    UnknownTypeInputBuffer as UIn,
+};
+use parking_lot::Mutex;
+use sample::Sample;
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::audio::{event_loop, playback, source};
+
+/// How much captured audio is kept around for the scopes (secs)
+const HISTORY_SECS: f32 = 2.0;
+
+/// How often the fake input device delivers audio (secs)
+const FILE_BLOCK_SECS: f32 = 0.01;
+
+#[derive(Debug, Snafu)]
+pub enum CreateError {
+    #[snafu(display("No input device available on host \"{:?}\"", host))]
+    NoInputDevice { host: cpal::HostId },
+
+    #[snafu(display("Audio device initialization panicked!"))]
+    InitializationPanic,
+
+    #[snafu(display("Failed to get input format for device: {}", source))]
+    NoInputFormats { source: cpal::DefaultFormatError },
+
+    #[snafu(display("Failed to open audio input stream: {}", source))]
+    StreamError { source: event_loop::OpenError },
+
+    #[snafu(display("Failed to start capture thread: {}", source))]
+    ThreadError { source: std::io::Error },
+
+    #[snafu(display("Failed to open input file: {}", source))]
+    FileLoadError { source: source::LoadError },
+
+    #[snafu(display("Failed to read input file: {}", source))]
+    FileReadError { source: source::ReadError },
+
+    #[snafu(display("Input file {} is empty", path.display()))]
+    EmptyFile { path: PathBuf },
+}
+
+fn input_device(
+    config: &crate::config::Audio,
+    host: &cpal::Host,
+) -> Result<cpal::Device, CreateError> {
+    let default = || {
+        host.default_input_device()
+            .context(NoInputDevice { host: host.id() })
+    };
+
+    match &config.input_device {
+        Some(dev_name) => match host.input_devices() {
+            Ok(mut iter) => match iter.find(|dev| {
+                dev.name()
+                    .ok()
+                    .map(|name| &name == dev_name)
+                    .unwrap_or(false)
+            }) {
+                Some(d) => Ok(d),
+                None => {
+                    tracing::warn!(
+                        "Input device \"{}\" does not exist ... using default",
+                        dev_name
+                    );
+                    default()
+                }
+            },
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to query input devices: {} ... attempting to use default",
+                    e
+                );
+                default()
+            }
+        },
+        None => default(),
+    }
+}
+
+// most recent interleaved samples, oldest first
+struct History {
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl History {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        let capacity = (sample_rate as f32 * HISTORY_SECS) as usize * channels as usize;
+        History {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, samples: impl Iterator<Item = f32>) {
+        self.samples.extend(samples);
+        let excess = self.samples.len().saturating_sub(self.capacity);
+        self.samples.drain(..excess);
+    }
+}
+
+/// Audio captured from an input device, or from a WAV file that is played in
+/// a loop as if it was captured
+pub struct Capture {
+    _stream: Option<event_loop::Stream>,
+    capture_thread: Option<thread::JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    history: Arc<Mutex<History>>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Capture {
+    /// Opens the input selected in the config, `input_file` takes precedence
+    /// over the input device
+    pub fn new(config: &crate::config::Config) -> Result<Self, CreateError> {
+        match &config.audio.input_file {
+            Some(path) => Capture::from_file(path),
+            None => Capture::from_device(&config.audio),
+        }
+    }
+
+    fn from_device(config: &crate::config::Audio) -> Result<Self, CreateError> {
+        let sp = tracing::debug_span!("create_capture");
+        let _e = sp.enter();
+
+        let config = config.clone();
+        tracing::debug!("Initializing audio input device");
+        let (host, device, format) = thread::Builder::new()
+            .name("capture init".into())
+            .spawn(move || {
+                let host = playback::audio_host(config.input_host.as_deref());
+                let device = input_device(&config, &host)?;
+                let format = device.default_input_format().context(NoInputFormats)?;
+                Ok((host, device, format))
+            })
+            .context(ThreadError)?
+            .join()
+            .ok()
+            .context(InitializationPanic)??;
+
+        let history = Arc::new(Mutex::new(History::new(
+            format.channels,
+            format.sample_rate.0,
+        )));
+
+        tracing::debug!(format = ?format, "Starting capture stream");
+        let thr_history = history.clone();
+        let stream = event_loop::Stream::input(&host, &device, &format, move |stream_data| {
+            let mut history = thr_history.lock();
+            match stream_data {
+                cpal::StreamData::Input {
+                    buffer: UIn::U16(buffer),
+                } => history.push(buffer.iter().map(|s| s.to_sample())),
+                cpal::StreamData::Input {
+                    buffer: UIn::I16(buffer),
+                } => history.push(buffer.iter().map(|s| s.to_sample())),
+                cpal::StreamData::Input {
+                    buffer: UIn::F32(buffer),
+                } => history.push(buffer.iter().copied()),
+                _ => {}
+            }
+        })
+        .context(StreamError)?;
+
+        Ok(Capture {
+            _stream: Some(stream),
+            capture_thread: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            history,
+            channels: format.channels,
+            sample_rate: format.sample_rate.0,
+        })
+    }
+
+    /// Fake input device that loops a WAV file in realtime, for testing live
+    /// mode without a capture device
+    fn from_file(path: &Path) -> Result<Self, CreateError> {
+        let sp = tracing::debug_span!("create_file_capture", path = ?path);
+        let _e = sp.enter();
+
+        let mut source = source::AudioSource::new(path.to_path_buf());
+        source.load().context(FileLoadError)?;
+        let mut loaded = source.as_loaded().unwrap();
+        let spec = loaded.spec();
+        let len = loaded.len() as usize;
+        let samples = loaded.chunk_at(0, len).context(FileReadError)?;
+        if samples.is_empty() {
+            return Err(CreateError::EmptyFile {
+                path: path.to_path_buf(),
+            });
+        }
+
+        let history = Arc::new(Mutex::new(History::new(spec.channels, spec.sample_rate)));
+
+        tracing::debug!(spec = ?spec, "Starting file capture thread");
+        let thr_history = history.clone();
+        let block_len =
+            ((spec.sample_rate as f32 * FILE_BLOCK_SECS) as usize).max(1) * spec.channels as usize;
+        let block_duration = time::Duration::from_secs_f32(FILE_BLOCK_SECS);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thr_stop = stop.clone();
+        let capture_thread = thread::Builder::new()
+            .name("audio capture".into())
+            .spawn(move || {
+                let sp = tracing::debug_span!("capture_thread");
+                let _e = sp.enter();
+
+                let mut pos = 0;
+                let mut deadline = time::Instant::now();
+                while !thr_stop.load(Ordering::Relaxed) {
+                    thr_history
+                        .lock()
+                        .push((0..block_len).map(|i| samples[(pos + i) % samples.len()]));
+                    pos = (pos + block_len) % samples.len();
+
+                    deadline += block_duration;
+                    let now = time::Instant::now();
+                    if deadline > now {
+                        thread::sleep(deadline - now);
+                    }
+                }
+            })
+            .context(ThreadError)?;
+
+        Ok(Capture {
+            _stream: None,
+            capture_thread: Some(capture_thread),
+            stop,
+            history,
+            channels: spec.channels,
+            sample_rate: spec.sample_rate,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The last `frames` captured frames as interleaved samples, padded with
+    /// silence at the start if not enough audio was captured yet
+    pub fn latest(&self, frames: usize) -> Vec<f32> {
+        let len = frames * self.channels as usize;
+        let history = self.history.lock();
+
+        let available = history.samples.len().min(len);
+        let skip = history.samples.len() - available;
+        let mut samples = vec![0f32; len - available];
+        samples.extend(history.samples.iter().skip(skip));
+        samples
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.capture_thread.take() {
+            if thread.join().is_err() {
+                tracing::error!("Audio capture thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Capture {
+    /// Waits until at least `frames` frames were captured, the file capture
+    /// thread may be slow to start on a busy machine
+    pub fn wait_for(&self, frames: usize) {
+        let deadline = time::Instant::now() + time::Duration::from_secs(10);
+        while self.history.lock().samples.len() < frames * self.channels as usize {
+            assert!(time::Instant::now() < deadline, "capture is stuck");
+            thread::sleep(time::Duration::from_millis(5));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const RATE: u32 = 1000;
+    const FRAMES: u32 = 100;
+
+    // stereo file where frame i is (i + 1, -(i + 1)), so that every sample
+    // tells where it came from
+    fn write_wav(name: &str) -> PathBuf {
+        let samples = (0..FRAMES).flat_map(|i| vec![i as f32 + 1.0, -(i as f32 + 1.0)]);
+        test_util::write_wav(name, 2, RATE, samples)
+    }
+
+    #[test]
+    fn file_capture_loops_the_file() {
+        let path = write_wav("capture");
+        let capture = Capture::from_file(&path).unwrap();
+        assert_eq!(capture.channels(), 2);
+        assert_eq!(capture.sample_rate(), RATE);
+
+        // long enough to wrap around the file at least once
+        capture.wait_for(FRAMES as usize * 2);
+
+        let frames = 1000;
+        let samples = capture.latest(frames);
+        assert_eq!(samples.len(), frames * 2);
+
+        // padded at the start, captured frames follow each other
+        let padding = samples.iter().take_while(|s| **s == 0.0).count();
+        assert_eq!(padding % 2, 0);
+        assert!(padding < samples.len(), "nothing was captured");
+
+        let captured = &samples[padding..];
+        assert!(captured.len() / 2 > FRAMES as usize);
+        for frame in captured.chunks(2) {
+            assert_eq!(frame[1], -frame[0]);
+        }
+        for pair in captured.chunks(2).collect::<Vec<_>>().windows(2) {
+            let (prev, next) = (pair[0][0], pair[1][0]);
+            let expected = if prev as u32 == FRAMES {
+                1.0
+            } else {
+                prev + 1.0
+            };
+            assert_eq!(next, expected);
+        }
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn latest_pads_with_silence() {
+        let mut history = History::new(2, RATE);
+        history.push([1.0, -1.0, 2.0, -2.0].iter().copied());
+        let capture = Capture {
+            _stream: None,
+            capture_thread: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            history: Arc::new(Mutex::new(history)),
+            channels: 2,
+            sample_rate: RATE,
+        };
+
+        assert_eq!(capture.latest(3), vec![0.0, 0.0, 1.0, -1.0, 2.0, -2.0]);
+        assert_eq!(capture.latest(1), vec![2.0, -2.0]);
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::audio::filter::DcFilter;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub enum MasterChannel {
     Left,
@@ -8,8 +10,16 @@ pub enum MasterChannel {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub enum ConnectionTarget {
-    Master { channel: MasterChannel },
-    Scope { name: String, channel: u32 },
+    Master {
+        channel: MasterChannel,
+    },
+    Scope {
+        name: String,
+        channel: u32,
+        // only applied to the scope input, master is left untouched
+        #[serde(default)]
+        filter: Option<DcFilter>,
+    },
 }
 
 impl ConnectionTarget {
@@ -19,6 +29,13 @@ impl ConnectionTarget {
             _ => false,
         }
     }
+
+    pub fn targets_scope(&self, scope_name: &str) -> bool {
+        match self {
+            ConnectionTarget::Scope { name, .. } => name == scope_name,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -0,0 +1,91 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Curve {
+    Linear,
+    Exponential,
+    EqualPower,
+    SCurve,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        Curve::Linear
+    }
+}
+
+impl Curve {
+    pub const ALL: [Curve; 4] = [
+        Curve::Linear,
+        Curve::Exponential,
+        Curve::EqualPower,
+        Curve::SCurve,
+    ];
+
+    /// Gain of a fade in at `x`, where `x` goes from 0 to 1
+    pub fn gain(self, x: f32) -> f32 {
+        let x = x.max(0.0).min(1.0);
+        match self {
+            Curve::Linear => x,
+            // 60dB of range, offset so that the curve starts at silence
+            Curve::Exponential => (1000f32.powf(x) - 1.0) / 999.0,
+            Curve::EqualPower => (x * PI / 2.0).sin(),
+            Curve::SCurve => (1.0 - (x * PI).cos()) / 2.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Curve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Curve::Linear => write!(f, "Linear"),
+            Curve::Exponential => write!(f, "Exponential"),
+            Curve::EqualPower => write!(f, "Equal Power"),
+            Curve::SCurve => write!(f, "S-Curve"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fade {
+    /// length in frames
+    pub frames: u32,
+    pub curve: Curve,
+}
+
+/// Gain envelope over the frames `start..end` of a source
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope {
+    pub start: i64,
+    pub end: i64,
+    pub fade_in: Option<Fade>,
+    pub fade_out: Option<Fade>,
+}
+
+impl Envelope {
+    pub fn gain(&self, frame: i64) -> f32 {
+        if frame < self.start || frame >= self.end {
+            return 0.0;
+        }
+
+        let mut gain = 1.0;
+
+        // the first frame of a fade in and the last frame of a fade out are silent
+        if let Some(fade) = self.fade_in.filter(|f| f.frames > 0) {
+            let pos = frame - self.start;
+            if pos < i64::from(fade.frames) {
+                gain *= fade.curve.gain(pos as f32 / fade.frames as f32);
+            }
+        }
+        if let Some(fade) = self.fade_out.filter(|f| f.frames > 0) {
+            let pos = self.end - 1 - frame;
+            if pos < i64::from(fade.frames) {
+                gain *= fade.curve.gain(pos as f32 / fade.frames as f32);
+            }
+        }
+
+        gain
+    }
+}
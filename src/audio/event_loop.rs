@@ -0,0 +1,127 @@
+//! cpal event loops never return once they run, so every host gets a single
+//! loop that is shared by all streams opened on it
+
+use std::sync::Arc;
+use std::thread;
+
+use cpal::traits::{EventLoopTrait, HostTrait};
+use parking_lot::{const_mutex, Mutex};
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum OpenError {
+    #[snafu(display("Failed to initialize audio stream: {}", source))]
+    BuildError { source: cpal::BuildStreamError },
+
+    #[snafu(display("Failed to start audio stream: {}", source))]
+    PlayError { source: cpal::PlayStreamError },
+
+    #[snafu(display("Failed to start audio event loop: {}", source))]
+    ThreadError { source: std::io::Error },
+}
+
+type Callback = Box<dyn FnMut(cpal::StreamData<'_>) + Send>;
+
+struct SharedLoop {
+    event_loop: cpal::EventLoop,
+    callbacks: Mutex<Vec<(cpal::StreamId, Callback)>>,
+}
+
+static LOOPS: Mutex<Vec<(cpal::HostId, Arc<SharedLoop>)>> = const_mutex(Vec::new());
+
+fn shared_loop(host: &cpal::Host) -> Result<Arc<SharedLoop>, OpenError> {
+    let mut loops = LOOPS.lock();
+    if let Some((_, shared)) = loops.iter().find(|(id, _)| *id == host.id()) {
+        return Ok(shared.clone());
+    }
+
+    let shared = Arc::new(SharedLoop {
+        event_loop: host.event_loop(),
+        callbacks: Mutex::new(Vec::new()),
+    });
+
+    tracing::debug!(host = ?host.id(), "Starting audio event loop");
+    let thr_shared = shared.clone();
+    thread::Builder::new()
+        .name("audio events".into())
+        .spawn(move || {
+            let sp = tracing::debug_span!("audio_event_loop");
+            let _e = sp.enter();
+
+            let shared = &thr_shared;
+            shared.event_loop.run(move |stream_id, stream_res| {
+                let mut callbacks = shared.callbacks.lock();
+                let callback = callbacks.iter_mut().find(|(id, _)| *id == stream_id);
+                match (stream_res, callback) {
+                    (Ok(data), Some((_, callback))) => callback(data),
+                    // the stream was destroyed in the meantime
+                    (Ok(_), None) => {}
+                    (Err(err), _) => tracing::error!("Audio stream error: {}", err),
+                }
+            })
+        })
+        .context(ThreadError)?;
+
+    loops.push((host.id(), shared.clone()));
+    Ok(shared)
+}
+
+/// Stream on the event loop of its host, destroyed when dropped along with
+/// its callback
+pub struct Stream {
+    shared: Arc<SharedLoop>,
+    id: cpal::StreamId,
+}
+
+impl Stream {
+    pub fn output(
+        host: &cpal::Host,
+        device: &cpal::Device,
+        format: &cpal::Format,
+        callback: impl FnMut(cpal::StreamData<'_>) + Send + 'static,
+    ) -> Result<Self, OpenError> {
+        Stream::open(host, callback, |ev| ev.build_output_stream(device, format))
+    }
+
+    pub fn input(
+        host: &cpal::Host,
+        device: &cpal::Device,
+        format: &cpal::Format,
+        callback: impl FnMut(cpal::StreamData<'_>) + Send + 'static,
+    ) -> Result<Self, OpenError> {
+        Stream::open(host, callback, |ev| ev.build_input_stream(device, format))
+    }
+
+    fn open(
+        host: &cpal::Host,
+        callback: impl FnMut(cpal::StreamData<'_>) + Send + 'static,
+        build: impl FnOnce(&cpal::EventLoop) -> Result<cpal::StreamId, cpal::BuildStreamError>,
+    ) -> Result<Self, OpenError> {
+        let shared = shared_loop(host)?;
+        let id = build(&shared.event_loop).context(BuildError)?;
+        shared
+            .callbacks
+            .lock()
+            .push((id.clone(), Box::new(callback)));
+
+        // destroyed again by dropping it if it fails to start
+        let stream = Stream { shared, id };
+        stream
+            .shared
+            .event_loop
+            .play_stream(stream.id.clone())
+            .context(PlayError)?;
+
+        Ok(stream)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.shared.event_loop.destroy_stream(self.id.clone());
+        self.shared
+            .callbacks
+            .lock()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
@@ -0,0 +1,66 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum DcFilter {
+    /// Subtracts the average of the window
+    Mean,
+    /// One-pole high-pass filter, cutoff is in hz
+    HighPass { cutoff: f32 },
+}
+
+impl DcFilter {
+    pub fn apply(self, samples: &mut [f32], sample_rate: u32) {
+        match self {
+            DcFilter::Mean => {
+                if samples.is_empty() {
+                    return;
+                }
+                let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+                for v in samples.iter_mut() {
+                    *v -= mean;
+                }
+            }
+            DcFilter::HighPass { cutoff } => highpass(samples, sample_rate, cutoff),
+        }
+    }
+}
+
+impl std::fmt::Display for DcFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DcFilter::Mean => write!(f, "Remove DC"),
+            DcFilter::HighPass { cutoff } => write!(f, "High-pass ({} Hz)", cutoff),
+        }
+    }
+}
+
+pub fn highpass(samples: &mut [f32], sample_rate: u32, cutoff: f32) {
+    let rc = 1.0 / (2.0 * PI * cutoff.max(f32::EPSILON));
+    let dt = 1.0 / sample_rate as f32;
+    let alpha = rc / (rc + dt);
+
+    // prime with the first sample so a DC offset doesn't cause a step at the
+    // start of the window
+    let mut last_in = samples.first().copied().unwrap_or(0.0);
+    let mut last_out = 0.0;
+    for v in samples.iter_mut() {
+        let x = *v;
+        last_out = alpha * (last_out + x - last_in);
+        last_in = x;
+        *v = last_out;
+    }
+}
+
+pub fn lowpass(samples: &mut [f32], sample_rate: u32, cutoff: f32) {
+    let rc = 1.0 / (2.0 * PI * cutoff.max(f32::EPSILON));
+    let dt = 1.0 / sample_rate as f32;
+    let alpha = dt / (rc + dt);
+
+    let mut last_out = samples.first().copied().unwrap_or(0.0);
+    for v in samples.iter_mut() {
+        last_out += alpha * (*v - last_out);
+        *v = last_out;
+    }
+}
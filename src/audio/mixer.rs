@@ -8,13 +8,19 @@ pub struct SubmissionBuilder {
 impl SubmissionBuilder {
     /// length is in secs
     pub fn create(&self, length: f32) -> Submission {
+        self.create_exact(length, |rate| ((rate as f32) * length) as usize)
+    }
+
+    /// Like `create`, but with the number of frames for each sample rate
+    /// given by `frames`
+    pub fn create_exact<F: Fn(u32) -> usize>(&self, length: f32, frames: F) -> Submission {
         tracing::trace!(length = %length, "Creating new mixer submission");
 
         let mut streams = HashMap::new();
 
         for rate in &self.rates {
             if !streams.contains_key(rate) {
-                let stream = vec![0f32; ((*rate as f32) * length) as usize * self.channels];
+                let stream = vec![0f32; frames(*rate) * self.channels];
                 streams.insert(*rate, stream);
             }
         }
@@ -56,6 +62,11 @@ impl Submission {
         }
     }
 
+    /// Length in secs
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
     pub fn length_of_channel(&self, rate: u32) -> Option<usize> {
         self.streams
             .get(&rate)
@@ -186,7 +197,12 @@ impl<I: Iterator<Item = Submission>> Iterator for Mixer<I> {
         if n_streams == 1 {
             Some(resampled_streams.next().unwrap())
         } else {
-            let chunk_len = (self.sample_rate as f32 * submission.length) as usize * self.channels;
+            // resampled lengths can differ by a frame
+            let resampled_streams = resampled_streams.collect::<Vec<_>>();
+            let chunk_len =
+                resampled_streams.iter().map(Vec::len).max().unwrap_or(
+                    (self.sample_rate as f32 * submission.length) as usize * self.channels,
+                );
             let mut chunk = vec![0f32; chunk_len];
 
             for stream in resampled_streams {
@@ -0,0 +1,46 @@
+/// Length of one overview bin (secs)
+const BIN_SECS: f32 = 0.01;
+
+/// Precomputed min/max peaks of a whole audio file, for drawing waveforms
+pub struct Overview {
+    frames_per_bin: u32,
+    peaks: Vec<(f32, f32)>,
+}
+
+impl Overview {
+    pub fn frames_per_bin(sample_rate: u32) -> u32 {
+        ((sample_rate as f32 * BIN_SECS) as u32).max(1)
+    }
+
+    pub fn new(frames_per_bin: u32) -> Self {
+        Overview {
+            frames_per_bin,
+            peaks: Vec::new(),
+        }
+    }
+
+    /// Adds the peaks of the next bin of interleaved samples
+    pub fn push_bin(&mut self, samples: &[f32]) {
+        let peak = samples
+            .iter()
+            .fold((0f32, 0f32), |(min, max), s| (min.min(*s), max.max(*s)));
+        self.peaks.push(peak);
+    }
+
+    /// Min and max over file frames `start..end`, None if the range is empty
+    pub fn range(&self, start: i64, end: i64) -> Option<(f32, f32)> {
+        let bin_len = i64::from(self.frames_per_bin);
+        let first = (start.max(0) / bin_len) as usize;
+        // always include at least the bin containing `start`
+        let last = ((end.max(0) + bin_len - 1) / bin_len).max(first as i64 + 1) as usize;
+
+        self.peaks
+            .get(first..last.min(self.peaks.len()))
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                p.iter().fold((0f32, 0f32), |(min, max), (lo, hi)| {
+                    (min.min(*lo), max.max(*hi))
+                })
+            })
+    }
+}
@@ -1,15 +1,23 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::thread;
+use std::{io, thread, time};
 
 use cpal::{
-    traits::{DeviceTrait, EventLoopTrait, HostTrait},
+    traits::{DeviceTrait, HostTrait},
     UnknownTypeOutputBuffer as UOut,
 };
 use parking_lot::Mutex;
 use sample::Sample;
 use snafu::{OptionExt, ResultExt, Snafu};
 
-use crate::audio::mixer;
+use crate::audio::{event_loop, mixer};
+use crate::config::OutputBackend;
+
+// format of the null and file backends
+const HEADLESS_CHANNELS: u16 = 2;
+const HEADLESS_SAMPLE_RATE: u32 = 48000;
 
 #[derive(Debug, Snafu)]
 pub enum CreateError {
@@ -25,18 +33,220 @@ pub enum CreateError {
     #[snafu(display("Could not create mixer: {}", source))]
     MixerError { source: samplerate::Error },
 
-    #[snafu(display("Failed to initialize audio output stream: {}", source))]
-    StreamCreateError { source: cpal::BuildStreamError },
-
-    #[snafu(display("Failed to start audio output stream: {}", source))]
-    StreamPlayError { source: cpal::PlayStreamError },
+    #[snafu(display("Failed to open audio output stream: {}", source))]
+    StreamError { source: event_loop::OpenError },
 
     #[snafu(display("Failed to start audio thread: {}", source))]
     ThreadError { source: std::io::Error },
+
+    #[snafu(display("Failed to create audio output file {}: {}", path.display(), source))]
+    FileCreateError { path: PathBuf, source: hound::Error },
 }
 
-fn audio_host(config: &crate::config::Audio) -> cpal::Host {
-    match &config.host {
+type SharedStream = Arc<Mutex<mixer::MixerStream<crossbeam_channel::IntoIter<mixer::Submission>>>>;
+
+// frames pulled by the output, and the size of its last buffer
+#[derive(Clone, Default)]
+struct Progress {
+    consumed: Arc<AtomicU64>,
+    device_buffer: Arc<AtomicU64>,
+}
+
+impl Progress {
+    fn played(&self, frames: u64) {
+        self.consumed.fetch_add(frames, Ordering::Relaxed);
+        self.device_buffer.store(frames, Ordering::Relaxed);
+    }
+
+    fn consumed(&self) -> u64 {
+        self.consumed.load(Ordering::Relaxed)
+    }
+
+    fn device_buffer(&self) -> u64 {
+        self.device_buffer.load(Ordering::Relaxed)
+    }
+}
+
+/// Common sample rates, the null and file outputs take any of them
+pub const SAMPLE_RATES: [u32; 6] = [32000, 44100, 48000, 88200, 96000, 192000];
+
+// other rates are only used in the default format of the device
+fn same_format(supported: &cpal::SupportedFormat, format: &cpal::Format) -> bool {
+    supported.channels == format.channels && supported.data_type == format.data_type
+}
+
+/// Rates the device can output in its default format, out of `SAMPLE_RATES`
+/// and the fixed rates it lists
+pub fn supported_sample_rates(device: &cpal::Device) -> Vec<u32> {
+    let format = match device.default_output_format() {
+        Ok(format) => format,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut rates = vec![format.sample_rate.0];
+    if let Ok(formats) = device.supported_output_formats() {
+        for f in formats.filter(|f| same_format(f, &format)) {
+            let (min, max) = (f.min_sample_rate.0, f.max_sample_rate.0);
+            if min == max {
+                rates.push(min);
+            }
+            rates.extend(SAMPLE_RATES.iter().filter(|r| min <= **r && **r <= max));
+        }
+    }
+    rates.sort();
+    rates.dedup();
+    rates
+}
+
+fn format_supports_rate(device: &cpal::Device, format: &cpal::Format, rate: u32) -> bool {
+    device
+        .supported_output_formats()
+        .map(|mut formats| {
+            formats.any(|f| {
+                same_format(&f, format)
+                    && f.min_sample_rate.0 <= rate
+                    && rate <= f.max_sample_rate.0
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the player can open the device at `rate`, which it does in the
+/// default format of the device
+pub fn supports_sample_rate(device: &cpal::Device, rate: u32) -> bool {
+    match device.default_output_format() {
+        Ok(format) => format_supports_rate(device, &format, rate),
+        Err(_) => false,
+    }
+}
+
+// default format of the device, at `sample_rate` if it supports it
+fn output_format(
+    device: &cpal::Device,
+    sample_rate: Option<u32>,
+) -> Result<cpal::Format, CreateError> {
+    let mut format = device.default_output_format().context(NoOutputFormats)?;
+
+    if let Some(rate) = sample_rate {
+        if format_supports_rate(device, &format, rate) {
+            format.sample_rate = cpal::SampleRate(rate);
+        } else {
+            tracing::warn!(
+                "Output device does not support {}hz, using {}hz",
+                rate,
+                format.sample_rate.0
+            );
+        }
+    }
+
+    Ok(format)
+}
+
+// lets the output be shut down when the player is replaced
+enum Output {
+    Device(event_loop::Stream),
+    Paced { stop: Arc<AtomicBool> },
+    Closed,
+}
+
+fn open_device_output(
+    host: &cpal::Host,
+    device: &cpal::Device,
+    format: &cpal::Format,
+    audio_stream: SharedStream,
+    progress: Progress,
+) -> Result<Output, CreateError> {
+    let channels = format.channels as usize;
+    let stream = event_loop::Stream::output(host, device, format, move |stream_data| {
+        let mut audio_stream = audio_stream.lock();
+
+        let written = match stream_data {
+            cpal::StreamData::Output {
+                buffer: UOut::U16(mut buffer),
+            } => {
+                for elem in buffer.iter_mut() {
+                    *elem = audio_stream.next().unwrap_or(0f32).to_sample();
+                }
+                buffer.len()
+            }
+            cpal::StreamData::Output {
+                buffer: UOut::I16(mut buffer),
+            } => {
+                for elem in buffer.iter_mut() {
+                    *elem = audio_stream.next().unwrap_or(0f32).to_sample();
+                }
+                buffer.len()
+            }
+            cpal::StreamData::Output {
+                buffer: UOut::F32(mut buffer),
+            } => {
+                for elem in buffer.iter_mut() {
+                    *elem = audio_stream.next().unwrap_or(0f32);
+                }
+                buffer.len()
+            }
+            _ => 0,
+        };
+
+        if written > 0 {
+            progress.played((written / channels) as u64);
+        }
+    })
+    .context(StreamError)?;
+
+    Ok(Output::Device(stream))
+}
+
+// pulls audio from the mixer at realtime pace, in place of an audio device
+fn spawn_paced_sink(
+    stream: SharedStream,
+    progress: Progress,
+    channels: u16,
+    sample_rate: u32,
+    buffer_ms: f32,
+    stop: Arc<AtomicBool>,
+    mut write: impl FnMut(&[f32]) -> Result<(), hound::Error> + Send + 'static,
+) -> io::Result<thread::JoinHandle<()>> {
+    let frames = ((sample_rate as f32 * buffer_ms / 1000.0) as usize).max(1);
+    let block_duration = time::Duration::from_secs_f64(frames as f64 / f64::from(sample_rate));
+
+    thread::Builder::new()
+        .name("audio playback".into())
+        .spawn(move || {
+            let sp = tracing::debug_span!("audio_thread");
+            let _e = sp.enter();
+
+            let mut block = vec![0f32; frames * channels as usize];
+            let mut deadline = time::Instant::now();
+            while !stop.load(Ordering::Relaxed) {
+                {
+                    let mut stream = stream.lock();
+                    for elem in block.iter_mut() {
+                        *elem = stream.next().unwrap_or(0f32);
+                    }
+                }
+
+                if let Err(e) = write(&block) {
+                    tracing::error!("Failed to write audio output: {}", e);
+                    return;
+                }
+                progress.played(frames as u64);
+
+                // a real device doesn't catch up after a stall either
+                deadline += block_duration;
+                let now = time::Instant::now();
+                if deadline > now {
+                    thread::sleep(deadline - now);
+                } else if now - deadline > block_duration {
+                    deadline = now;
+                }
+            }
+        })
+}
+
+/// Host with the given name, or the default host if it isn't available
+pub fn audio_host(name: Option<&str>) -> cpal::Host {
+    match name {
         Some(host_name) => {
             if let Some((id, _n)) = cpal::available_hosts()
                 .iter()
@@ -96,13 +306,31 @@ fn audio_device(
             .context(NoOutputDevice { host: host.id() }),
     }
 }
+
+// submitted master audio and the project position it was read from
+struct Marker {
+    /// output frame where the submission starts
+    start: u64,
+    /// length in output frames
+    len: u64,
+    /// project frame at the start of the submission
+    position: f64,
+    /// project frames covered by the submission
+    advance: f64,
+}
+
 pub struct Player {
-    _audio_thread: thread::JoinHandle<()>,
+    audio_thread: Option<thread::JoinHandle<()>>,
+    output: Output,
     submission_builder: mixer::SubmissionBuilder,
     submission_queue: crossbeam_channel::Sender<mixer::Submission>,
-    mixer_stream: Arc<Mutex<mixer::MixerStream<crossbeam_channel::IntoIter<mixer::Submission>>>>,
+    mixer_stream: SharedStream,
     channels: u16,
     sample_rate: u32,
+
+    progress: Progress,
+    submitted: u64,
+    markers: VecDeque<Marker>,
 }
 
 impl Player {
@@ -111,98 +339,117 @@ impl Player {
         let _e = sp.enter();
 
         let config = config.audio.clone();
-        tracing::debug!("Initializing audio device");
-        let (host, device, format) = thread::Builder::new()
-            .name("audio init".into())
-            .spawn(move || {
-                let host = audio_host(&config);
-                let device = audio_device(&config, &host)?;
-                let format = device.default_output_format().context(NoOutputFormats)?;
-                Ok((host, device, format))
-            })
-            .context(ThreadError)?
-            .join()
-            .ok()
-            .context(InitializationPanic)??;
+        let device = match config.backend {
+            OutputBackend::Device => {
+                tracing::debug!("Initializing audio device");
+                let thr_config = config.clone();
+                let (host, device, format) = thread::Builder::new()
+                    .name("audio init".into())
+                    .spawn(move || {
+                        let host = audio_host(thr_config.host.as_deref());
+                        let device = audio_device(&thr_config, &host)?;
+                        let format = output_format(&device, thr_config.sample_rate)?;
+                        Ok((host, device, format))
+                    })
+                    .context(ThreadError)?
+                    .join()
+                    .ok()
+                    .context(InitializationPanic)??;
+                Some((host, device, format))
+            }
+            OutputBackend::Null | OutputBackend::File => None,
+        };
+
+        let (channels, sample_rate) = match &device {
+            Some((_, _, format)) => (format.channels, format.sample_rate.0),
+            None => (
+                HEADLESS_CHANNELS,
+                config.sample_rate.unwrap_or(HEADLESS_SAMPLE_RATE),
+            ),
+        };
 
         let (submission_queue, sub_rx) = crossbeam_channel::bounded(0);
         let mut mixer_builder = mixer::MixerBuilder::new();
-        mixer_builder.channels(format.channels as usize);
-        mixer_builder.target_sample_rate(format.sample_rate.0);
+        mixer_builder.channels(channels as usize);
+        mixer_builder.target_sample_rate(sample_rate);
         let mixer = mixer_builder
             .build(sub_rx.into_iter())
             .context(MixerError)?;
         let submission_builder = mixer.submission_builder();
         let mixer_stream = Arc::new(Mutex::new(mixer.into_stream()));
 
-        tracing::debug!(format = ?format, "Starting audio thread");
         let audio_stream = mixer_stream.clone();
-        let thr_format = format.clone();
-        let audio_thread = thread::Builder::new()
-            .name("audio playback".into())
-            .spawn(move || {
-                let sp = tracing::debug_span!("audio_thread");
-                let _e = sp.enter();
-
-                let ev = host.event_loop();
-                let res: Result<(), CreateError> = (move || {
-                    let stream_id = ev
-                        .build_output_stream(&device, &thr_format)
-                        .context(StreamCreateError)?;
-
-                    ev.play_stream(stream_id).context(StreamPlayError)?;
-
-                    ev.run(move |_stream_id, stream_res| {
-                        let stream_data = match stream_res {
-                            Ok(data) => data,
-                            Err(err) => {
-                                tracing::error!("Audio playback stream error: {}", err);
-                                return;
-                            }
-                        };
-
-                        let mut audio_stream = audio_stream.lock();
-
-                        match stream_data {
-                            cpal::StreamData::Output {
-                                buffer: UOut::U16(mut buffer),
-                            } => {
-                                for elem in buffer.iter_mut() {
-                                    *elem = audio_stream.next().unwrap_or(0f32).to_sample();
-                                }
-                            }
-                            cpal::StreamData::Output {
-                                buffer: UOut::I16(mut buffer),
-                            } => {
-                                for elem in buffer.iter_mut() {
-                                    *elem = audio_stream.next().unwrap_or(0f32).to_sample();
-                                }
-                            }
-                            cpal::StreamData::Output {
-                                buffer: UOut::F32(mut buffer),
-                            } => {
-                                for elem in buffer.iter_mut() {
-                                    *elem = audio_stream.next().unwrap_or(0f32);
-                                }
-                            }
-                            _ => (),
-                        }
-                    });
-                })();
+        let progress = Progress::default();
 
-                if let Err(e) = res {
-                    tracing::error!("Unexpected audio thread error! {}", e);
-                }
-            })
-            .context(ThreadError)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (audio_thread, output) = match (device, config.backend) {
+            (Some((host, device, format)), _) => (
+                None,
+                open_device_output(&host, &device, &format, audio_stream, progress.clone())?,
+            ),
+            (None, OutputBackend::File) => {
+                let path = config.output_file;
+                tracing::debug!(path = ?path, "Starting audio file output");
+                let spec = hound::WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample: 32,
+                    sample_format: hound::SampleFormat::Float,
+                };
+                let mut writer = hound::WavWriter::create(&path, spec)
+                    .context(FileCreateError { path: path.clone() })?;
+
+                // keep the header valid, the app may exit without dropping us
+                let flush_interval = time::Duration::from_secs(1);
+                let mut flush_timer = time::Instant::now();
+                let thread = spawn_paced_sink(
+                    audio_stream,
+                    progress.clone(),
+                    channels,
+                    sample_rate,
+                    config.buffer_ms,
+                    stop.clone(),
+                    move |block| {
+                        for sample in block {
+                            writer.write_sample(*sample)?;
+                        }
+                        if flush_timer.elapsed() > flush_interval {
+                            flush_timer = time::Instant::now();
+                            writer.flush()?;
+                        }
+                        Ok(())
+                    },
+                )
+                .context(ThreadError)?;
+                (Some(thread), Output::Paced { stop })
+            }
+            (None, _) => {
+                tracing::debug!("Starting null audio output");
+                let thread = spawn_paced_sink(
+                    audio_stream,
+                    progress.clone(),
+                    channels,
+                    sample_rate,
+                    config.buffer_ms,
+                    stop.clone(),
+                    |_| Ok(()),
+                )
+                .context(ThreadError)?;
+                (Some(thread), Output::Paced { stop })
+            }
+        };
 
         Ok(Player {
-            _audio_thread: audio_thread,
+            audio_thread,
+            output,
             submission_builder,
             submission_queue,
             mixer_stream,
-            channels: format.channels,
-            sample_rate: format.sample_rate.0,
+            channels,
+            sample_rate,
+            progress,
+            submitted: 0,
+            markers: VecDeque::new(),
         })
     }
 
@@ -228,13 +475,194 @@ impl Player {
         self.submission_queue = submission_queue;
         *self.mixer_stream.lock() = mixer_stream;
 
+        // anything still queued was dropped with the old mixer
+        self.submitted = self.progress.consumed();
+        self.markers.clear();
+
         Ok(())
     }
 
+    /// Queues audio for playback. `frames` is how many output frames the
+    /// submission plays for, `position` is the project frame the audio was
+    /// read from and how many project frames it covers, if it was read from
+    /// the project at all.
     pub fn submit(
-        &self,
+        &mut self,
         sub: mixer::Submission,
+        frames: u64,
+        position: Option<(f64, f64)>,
     ) -> Result<(), crossbeam_channel::SendError<mixer::Submission>> {
-        self.submission_queue.send(sub)
+        self.submission_queue.send(sub)?;
+
+        if let Some((position, advance)) = position {
+            self.markers.push_back(Marker {
+                start: self.submitted,
+                len: frames,
+                position,
+                advance,
+            });
+        }
+        self.submitted += frames;
+
+        Ok(())
+    }
+
+    /// Output frames that were submitted but not played yet
+    pub fn queued_frames(&self) -> u64 {
+        self.submitted.saturating_sub(self.progress.consumed())
+    }
+
+    /// Project position of the audio that is being heard right now. `offset`
+    /// is added to the estimated output latency (secs).
+    pub fn heard_position(&mut self, offset: f32) -> Option<f64> {
+        let latency =
+            self.progress.device_buffer() as i64 + (offset * self.sample_rate as f32) as i64;
+        let heard = self.progress.consumed() as i64 - latency;
+
+        // forget submissions that have been heard completely
+        while self.markers.len() > 1 && self.markers[1].start as i64 <= heard {
+            self.markers.pop_front();
+        }
+
+        let marker = self.markers.front()?;
+        let into = heard - marker.start as i64;
+        if into < 0 {
+            return None;
+        }
+        let fraction = (into as f64 / marker.len.max(1) as f64).min(1.0);
+        Some(marker.position + marker.advance * fraction)
+    }
+
+    /// Stops the output, e.g. to release the audio device before opening it
+    /// again. Nothing is played afterwards.
+    pub fn close(&mut self) {
+        match std::mem::replace(&mut self.output, Output::Closed) {
+            // also drops the callback that holds on to the mixer
+            Output::Device(stream) => drop(stream),
+            Output::Paced { stop } => {
+                stop.store(true, Ordering::Relaxed);
+
+                // wakes the thread if it waits for a submission
+                self.submission_queue = crossbeam_channel::bounded(0).0;
+
+                // the file output is finalized when the thread exits, which
+                // has to happen before the file can be created again
+                if let Some(thread) = self.audio_thread.take() {
+                    if thread.join().is_err() {
+                        tracing::error!("Audio output thread panicked");
+                    }
+                }
+            }
+            Output::Closed => {}
+        }
+    }
+
+    /// Forgets where queued audio came from, e.g. after seeking
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const RATE: u32 = 1000;
+    // 10 frames per block
+    const BUFFER_MS: f32 = 10.0;
+
+    fn headless_player(backend: OutputBackend, output_file: PathBuf) -> Player {
+        let mut config = crate::config::Config::default();
+        config.audio.backend = backend;
+        config.audio.output_file = output_file;
+        config.audio.sample_rate = Some(RATE);
+        config.audio.buffer_ms = BUFFER_MS;
+
+        let mut player = Player::new(&config).unwrap();
+        assert_eq!(player.channels(), HEADLESS_CHANNELS);
+        assert_eq!(player.sample_rate(), RATE);
+
+        let mut builder = mixer::MixerBuilder::new();
+        builder
+            .channels(HEADLESS_CHANNELS as usize)
+            .target_sample_rate(RATE)
+            .source_rate(RATE);
+        player.rebuild_mixer(builder).unwrap();
+        player
+    }
+
+    // stereo audio where frame i is (i + 1, -(i + 1))
+    fn submit_frames(player: &mut Player, frames: usize) {
+        let mut sub = player
+            .submission_builder()
+            .create_exact(frames as f32 / RATE as f32, |_| frames);
+        sub.add(RATE, 0, (0..frames).map(|i| i as f32 + 1.0));
+        sub.add(RATE, 1, (0..frames).map(|i| -(i as f32 + 1.0)));
+        player.submit(sub, frames as u64, None).unwrap();
+    }
+
+    // the output may still count silence from before the mixer was rebuilt,
+    // so a block of silence is queued after the audio that has to be played
+    fn wait_until_played(player: &mut Player) {
+        let silence = player.submission_builder().create(BUFFER_MS / 1000.0);
+        let frames = (RATE as f32 * BUFFER_MS / 1000.0) as u64;
+        player.submit(silence, frames, None).unwrap();
+
+        let deadline = time::Instant::now() + time::Duration::from_secs(10);
+        while player.queued_frames() > 0 {
+            assert!(time::Instant::now() < deadline, "output is stuck");
+            thread::sleep(time::Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn file_output_writes_submitted_audio() {
+        let path = test_util::temp_path("player", "wav");
+        let mut player = headless_player(OutputBackend::File, path.clone());
+
+        submit_frames(&mut player, 25);
+        wait_until_played(&mut player);
+        player.close();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, HEADLESS_CHANNELS);
+        assert_eq!(spec.sample_rate, RATE);
+
+        // whole blocks are written, silent until the audio arrives
+        let samples = reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(samples.len() % 20, 0);
+        let start = samples.iter().take_while(|s| **s == 0.0).count();
+        assert!(samples.len() >= start + 50, "audio is missing");
+
+        for (i, frame) in samples[start..start + 50].chunks(2).enumerate() {
+            assert_eq!(frame, [i as f32 + 1.0, -(i as f32 + 1.0)]);
+        }
+        assert!(samples[start + 50..].iter().all(|s| *s == 0.0));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn null_output_plays_at_realtime_pace() {
+        let mut player = headless_player(OutputBackend::Null, PathBuf::new());
+
+        let started = time::Instant::now();
+        submit_frames(&mut player, 50);
+        wait_until_played(&mut player);
+        assert!(started.elapsed() >= time::Duration::from_millis(20));
+
+        player.close();
+        assert_eq!(player.queued_frames(), 0);
     }
 }
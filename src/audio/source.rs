@@ -1,12 +1,14 @@
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
 
 use sample::{types::I24, Sample};
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 
-use crate::audio;
+use crate::audio::{self, envelope, overview::Overview};
 
 #[derive(Debug, Snafu)]
 pub enum LoadError {
@@ -32,51 +34,237 @@ pub enum ReadError {
 #[derive(Deserialize, Serialize)]
 pub struct AudioSource {
     pub path: PathBuf,
+    /// Position in the file where playback starts (secs)
+    #[serde(default)]
+    pub start: Option<f32>,
+    /// Position in the file where playback ends (secs)
+    #[serde(default)]
+    pub end: Option<f32>,
+    /// Position of the trimmed source on the project timeline (secs)
+    #[serde(default)]
+    pub offset: f32,
     pub fade_in: Option<f32>,
     pub fade_out: Option<f32>,
+    #[serde(default)]
+    pub fade_in_curve: envelope::Curve,
+    #[serde(default)]
+    pub fade_out_curve: envelope::Curve,
     pub connections: Vec<audio::connection::Connection>,
 
     #[serde(skip)]
-    pub wav_reader: Option<hound::WavReader<io::BufReader<fs::File>>>,
+    pub wav_reader: Option<WavReader>,
     #[serde(skip)]
-    reader_position: u32,
+    overview: OverviewState,
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+}
+
+// reading the whole file takes a while, so the overview is only computed once
+// something shows it
+enum OverviewState {
+    Unrequested,
+    Pending(crossbeam_channel::Receiver<Overview>),
+    Ready(Overview),
+    // not retried until the file is loaded again
+    Failed,
+}
+
+impl Default for OverviewState {
+    fn default() -> Self {
+        OverviewState::Unrequested
+    }
+}
+
+type WavReader = hound::WavReader<io::BufReader<fs::File>>;
+
+fn open_wav(path: &Path) -> Result<WavReader, LoadError> {
+    let file = fs::File::open(path).context(OpenError {
+        path: path.to_path_buf(),
+    })?;
+    hound::WavReader::new(io::BufReader::new(file)).context(WavError {
+        path: path.to_path_buf(),
+    })
+}
+
+// computes the overview on its own reader in the background
+fn spawn_overview(path: PathBuf) -> Option<crossbeam_channel::Receiver<Overview>> {
+    let (overview_tx, overview_rx) = crossbeam_channel::bounded(1);
+    let spawned = thread::Builder::new()
+        .name("overview".into())
+        .spawn(move || {
+            let sp = tracing::debug_span!("compute_overview", path = ?path);
+            let _e = sp.enter();
+
+            let overview = open_wav(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|mut reader| compute_overview(&mut reader).map_err(|e| e.to_string()));
+            match overview {
+                // the source may have been reloaded or dropped in the meantime
+                Ok(overview) => {
+                    let _ = overview_tx.send(overview);
+                }
+                Err(e) => tracing::warn!("Failed to compute waveform overview: {}", e),
+            }
+        });
+
+    match spawned {
+        Ok(_) => Some(overview_rx),
+        Err(e) => {
+            tracing::warn!("Failed to start waveform overview thread: {}", e);
+            None
+        }
+    }
+}
+
+fn compute_overview(reader: &mut WavReader) -> Result<Overview, ReadError> {
+    let spec = reader.spec();
+    let frames_per_bin = Overview::frames_per_bin(spec.sample_rate);
+    let bin_len = frames_per_bin as usize * spec.channels as usize;
+
+    reader.seek(0).context(SeekError { pos: 0u32 })?;
+
+    let mut overview = Overview::new(frames_per_bin);
+    loop {
+        let samples = read_samples(reader, bin_len)?;
+        if !samples.is_empty() {
+            overview.push_bin(&samples);
+        }
+        if samples.len() < bin_len {
+            break;
+        }
+    }
+
+    Ok(overview)
 }
 
 impl AudioSource {
+    pub fn new(path: PathBuf) -> Self {
+        AudioSource {
+            path,
+            start: None,
+            end: None,
+            offset: 0.0,
+            fade_in: None,
+            fade_out: None,
+            fade_in_curve: Default::default(),
+            fade_out_curve: Default::default(),
+            connections: Vec::new(),
+            wav_reader: None,
+            overview: OverviewState::Unrequested,
+            modified: None,
+        }
+    }
+
     pub fn load(&mut self) -> Result<(), LoadError> {
         let sp = tracing::trace_span!("load_source", source = %self.path.file_name().unwrap().to_string_lossy());
         let _e = sp.enter();
 
-        let file = fs::File::open(&self.path).context(OpenError {
-            path: self.path.clone(),
-        })?;
-
-        let wav_reader = hound::WavReader::new(io::BufReader::new(file)).context(WavError {
-            path: self.path.clone(),
-        })?;
+        // recorded even if loading fails so that broken files aren't retried
+        // until they change again
+        self.modified = self.modified_on_disk();
 
-        self.wav_reader = Some(wav_reader);
+        self.wav_reader = Some(open_wav(&self.path)?);
+        self.overview = OverviewState::Unrequested;
 
         Ok(())
     }
 
     pub fn unload(&mut self) {
         self.wav_reader = None;
+        self.overview = OverviewState::Unrequested;
+    }
+
+    pub fn reload(&mut self) -> Result<(), LoadError> {
+        let old_spec = self.wav_reader.as_ref().map(|r| r.spec());
+
+        self.unload();
+        self.load()?;
+
+        let new_spec = self.wav_reader.as_ref().map(|r| r.spec());
+        if let (Some(old), Some(new)) = (old_spec, new_spec) {
+            if old.sample_rate != new.sample_rate || old.channels != new.channels {
+                tracing::warn!(
+                    "{} changed from {}hz/{}ch to {}hz/{}ch",
+                    self.path.display(),
+                    old.sample_rate,
+                    old.channels,
+                    new.sample_rate,
+                    new.channels
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes over the loaded file of another source with the same path
+    pub fn take_reader(&mut self, other: AudioSource) {
+        self.wav_reader = other.wav_reader;
+        self.overview = other.overview;
+        self.modified = other.modified;
+    }
+
+    fn modified_on_disk(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Whether the file has changed since it was last loaded
+    pub fn changed_on_disk(&self) -> bool {
+        match self.modified_on_disk() {
+            Some(modified) => self.modified != Some(modified),
+            None => false,
+        }
     }
 
     pub fn is_loaded(&self) -> bool {
         self.wav_reader.is_some()
     }
 
+    /// Starts computing the waveform overview in the background if that
+    /// didn't happen yet, `as_loaded` has it once it's done
+    pub fn request_overview(&mut self) {
+        if !self.is_loaded() {
+            return;
+        }
+        if let OverviewState::Unrequested = self.overview {
+            self.overview = match spawn_overview(self.path.clone()) {
+                Some(overview_rx) => OverviewState::Pending(overview_rx),
+                None => OverviewState::Failed,
+            };
+        }
+    }
+
+    // picks up the overview once it's done
+    fn poll_overview(&mut self) {
+        if let OverviewState::Pending(overview_rx) = &self.overview {
+            let state = match overview_rx.try_recv() {
+                Ok(overview) => OverviewState::Ready(overview),
+                Err(crossbeam_channel::TryRecvError::Disconnected) => OverviewState::Failed,
+                Err(crossbeam_channel::TryRecvError::Empty) => return,
+            };
+            self.overview = state;
+        }
+    }
+
     pub fn as_loaded(&mut self) -> Option<AsLoaded> {
+        self.poll_overview();
+
         if let Some(wav_reader) = self.wav_reader.as_mut() {
             Some(AsLoaded {
                 path: self.path.as_path(),
+                start: self.start,
+                end: self.end,
+                offset: self.offset,
                 fade_in: self.fade_in,
                 fade_out: self.fade_out,
+                fade_in_curve: self.fade_in_curve,
+                fade_out_curve: self.fade_out_curve,
                 connections: self.connections.as_slice(),
+                overview: match &self.overview {
+                    OverviewState::Ready(overview) => Some(overview),
+                    _ => None,
+                },
                 wav_reader,
-                reader_position: &mut self.reader_position,
             })
         } else {
             None
@@ -86,11 +274,16 @@ impl AudioSource {
 
 pub struct AsLoaded<'a> {
     path: &'a Path,
+    pub start: Option<f32>,
+    pub end: Option<f32>,
+    pub offset: f32,
     pub fade_in: Option<f32>,
     pub fade_out: Option<f32>,
+    pub fade_in_curve: envelope::Curve,
+    pub fade_out_curve: envelope::Curve,
     pub connections: &'a [audio::connection::Connection],
-    wav_reader: &'a mut hound::WavReader<io::BufReader<fs::File>>,
-    reader_position: &'a mut u32,
+    pub overview: Option<&'a Overview>,
+    wav_reader: &'a mut WavReader,
 }
 
 impl<'a> AsLoaded<'a> {
@@ -102,124 +295,288 @@ impl<'a> AsLoaded<'a> {
         self.wav_reader.spec()
     }
 
+    fn secs_to_frames(&self, secs: f32) -> i64 {
+        (secs * self.spec().sample_rate as f32).round() as i64
+    }
+
+    /// First frame of the file that is played back
+    pub fn start_frame(&self) -> u32 {
+        let start = self
+            .start
+            .map(|s| self.secs_to_frames(s.max(0.0)))
+            .unwrap_or(0);
+        (start as u32).min(self.wav_reader.duration())
+    }
+
+    /// Frame of the file where playback stops (exclusive)
+    pub fn end_frame(&self) -> u32 {
+        let duration = self.wav_reader.duration();
+        let end = match self.end {
+            Some(e) => (self.secs_to_frames(e.max(0.0)) as u32).min(duration),
+            None => duration,
+        };
+        end.max(self.start_frame())
+    }
+
+    /// Trimmed length in frames
+    pub fn duration(&self) -> u32 {
+        self.end_frame() - self.start_frame()
+    }
+
+    /// Trimmed length in samples (across all channels)
     pub fn len(&self) -> u32 {
-        self.wav_reader.len()
+        self.duration() * u32::from(self.spec().channels)
+    }
+
+    /// Timeline frame where the source begins
+    pub fn timeline_start(&self) -> i64 {
+        self.secs_to_frames(self.offset)
     }
 
+    /// Timeline frame where the source ends (exclusive)
+    pub fn timeline_end(&self) -> i64 {
+        self.timeline_start() + i64::from(self.duration())
+    }
+
+    pub fn envelope(&self) -> envelope::Envelope {
+        let fade = |len: Option<f32>, curve| {
+            len.map(|l| envelope::Fade {
+                frames: self.secs_to_frames(l.max(0.0)) as u32,
+                curve,
+            })
+        };
+
+        envelope::Envelope {
+            start: i64::from(self.start_frame()),
+            end: i64::from(self.end_frame()),
+            fade_in: fade(self.fade_in, self.fade_in_curve),
+            fade_out: fade(self.fade_out, self.fade_out_curve),
+        }
+    }
+
+    /// Reads `len` interleaved samples starting at timeline frame `pos`.
+    /// Anything outside of the trimmed region of the file is silent.
     pub fn chunk_at(&mut self, pos: u32, len: usize) -> Result<Vec<f32>, ReadError> {
-        tracing::trace!(pos = pos, "Seeking WavReader");
-        self.wav_reader.seek(pos).context(SeekError { pos })?;
-        *self.reader_position = pos;
-        self.next_chunk(len)
+        let spec = self.spec();
+        let channels = spec.channels as usize;
+        let frames = ((len + channels - 1) / channels) as i64;
+
+        let start_frame = i64::from(self.start_frame());
+        let end_frame = i64::from(self.end_frame());
+
+        // map timeline position to file position
+        let file_pos = i64::from(pos) - self.timeline_start() + start_frame;
+        let read_start = file_pos.max(start_frame).min(end_frame);
+        let read_end = (file_pos + frames).max(start_frame).min(end_frame);
+
+        let mut chunk = vec![0f32; len];
+        if read_end <= read_start {
+            return Ok(chunk);
+        }
+
+        tracing::trace!(pos = read_start, "Seeking WavReader");
+        self.wav_reader.seek(read_start as u32).context(SeekError {
+            pos: read_start as u32,
+        })?;
+        let samples = self.read_samples((read_end - read_start) as usize * channels)?;
+
+        let envelope = self.envelope();
+
+        let chunk_offset = (read_start - file_pos) as usize * channels;
+        for (i, (v, s)) in chunk.iter_mut().skip(chunk_offset).zip(samples).enumerate() {
+            let frame = read_start + (i / channels) as i64;
+            *v = s * envelope.gain(frame);
+        }
+
+        Ok(chunk)
     }
 
-    fn fade(
-        spec: hound::WavSpec,
-        reader_pos: u32,
-        len: u32,
-        in_len: Option<f32>,
-        out_len: Option<f32>,
-    ) -> impl Fn((usize, Result<f32, hound::Error>)) -> Result<f32, hound::Error> {
-        move |(idx, samp)| {
-            let mut s = samp?;
+    fn read_samples(&mut self, len: usize) -> Result<Vec<f32>, ReadError> {
+        read_samples(self.wav_reader, len)
+    }
+}
 
-            let len = len * u32::from(spec.channels);
-            let idx = (idx + reader_pos as usize) / spec.channels as usize * spec.channels as usize;
+fn read_samples(reader: &mut WavReader, len: usize) -> Result<Vec<f32>, ReadError> {
+    let spec = reader.spec();
+
+    let sp = tracing::trace_span!("get_chunk", len = len);
+    let _e = sp.enter();
+
+    match spec.sample_format {
+        hound::SampleFormat::Int => match spec.bits_per_sample {
+            8 => reader
+                .samples()
+                .take(len)
+                .map(|v| v.map(i8::to_sample))
+                .collect::<Result<Vec<f32>, hound::Error>>()
+                .context(DecodeError),
+            16 => reader
+                .samples()
+                .take(len)
+                .map(|v| v.map(i16::to_sample))
+                .collect::<Result<Vec<f32>, hound::Error>>()
+                .context(DecodeError),
+            24 => reader
+                .samples::<i32>()
+                .take(len)
+                .map(|v| v.map(I24::new_unchecked).map(I24::to_sample))
+                .collect::<Result<Vec<f32>, hound::Error>>()
+                .context(DecodeError),
+            v => Err(ReadError::UnsupportedDepth { depth: v }),
+        },
+        hound::SampleFormat::Float => match spec.bits_per_sample {
+            32 => reader
+                .samples()
+                .take(len)
+                .collect::<Result<Vec<f32>, hound::Error>>()
+                .context(DecodeError),
+            v => Err(ReadError::UnsupportedDepth { depth: v }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::envelope::Curve;
+    use crate::test_util;
+    use std::time::{Duration, Instant};
+
+    const RATE: u32 = 1000;
+    const AMPLITUDE: f32 = 0.5;
 
-            let in_samps = (in_len.unwrap_or(0.0) * spec.sample_rate as f32) as usize;
-            let out_samps = (out_len.unwrap_or(0.0) * spec.sample_rate as f32) as usize;
+    // constant amplitude mono file, so every sample is AMPLITUDE * gain
+    fn write_wav(name: &str, frames: u32) -> PathBuf {
+        test_util::write_wav(name, 1, RATE, (0..frames).map(|_| AMPLITUDE))
+    }
 
-            s *= (idx as f32 / in_samps as f32).max(0.0).min(1.0);
-            s *= ((len as usize - idx) as f32 / out_samps as f32)
-                .max(0.0)
-                .min(1.0);
+    fn assert_gain(chunk: &[f32], i: usize, gain: f32) {
+        let expected = AMPLITUDE * gain;
+        assert!(
+            (chunk[i] - expected).abs() < 1e-6,
+            "sample {} is {}, expected {}",
+            i,
+            chunk[i],
+            expected
+        );
+    }
 
-            Ok(s)
+    #[test]
+    fn fades_and_trim() {
+        let path = write_wav("fades", 100);
+
+        for &curve in Curve::ALL.iter() {
+            // file frames 10..90 play from timeline frame 5, fading in over 20
+            // frames and out over 30
+            let mut source = AudioSource::new(path.clone());
+            source.start = Some(0.01);
+            source.end = Some(0.09);
+            source.offset = 0.005;
+            source.fade_in = Some(0.02);
+            source.fade_out = Some(0.03);
+            source.fade_in_curve = curve;
+            source.fade_out_curve = curve;
+            source.load().unwrap();
+
+            let mut loaded = source.as_loaded().unwrap();
+            let chunk = loaded.chunk_at(0, 100).unwrap();
+
+            // trimmed off before the start and after the end
+            for i in (0..5).chain(85..100) {
+                assert_gain(&chunk, i, 0.0);
+            }
+
+            // the first frame of the fade in and the last of the fade out are
+            // silent, fully audible right after the fades
+            assert_gain(&chunk, 5, 0.0);
+            assert_gain(&chunk, 84, 0.0);
+            assert_gain(&chunk, 25, 1.0);
+            assert_gain(&chunk, 54, 1.0);
+
+            for k in 0..20 {
+                assert_gain(&chunk, 5 + k, curve.gain(k as f32 / 20.0));
+            }
+            for k in 0..30 {
+                assert_gain(&chunk, 84 - k, curve.gain(k as f32 / 30.0));
+            }
+            for i in 25..55 {
+                assert_gain(&chunk, i, 1.0);
+            }
+
+            // reading from the middle gives the same gain per frame
+            let part = loaded.chunk_at(20, 10).unwrap();
+            for (i, v) in part.iter().enumerate() {
+                assert_eq!(*v, chunk[20 + i]);
+            }
         }
+
+        let _ = fs::remove_file(path);
     }
 
-    // cursed
-    pub fn next_chunk(&mut self, len: usize) -> Result<Vec<f32>, ReadError> {
-        let spec = self.spec();
-        let total_len = self.len();
+    #[test]
+    fn overlapping_fades() {
+        let path = write_wav("overlap", 100);
+
+        for &curve in Curve::ALL.iter() {
+            // 40 frames long, both fades are 30 frames
+            let mut source = AudioSource::new(path.clone());
+            source.end = Some(0.04);
+            source.fade_in = Some(0.03);
+            source.fade_out = Some(0.03);
+            source.fade_in_curve = curve;
+            source.fade_out_curve = Curve::Linear;
+            source.load().unwrap();
+
+            let chunk = source.as_loaded().unwrap().chunk_at(0, 50).unwrap();
+            for i in 0..40 {
+                let fade_in = if i < 30 {
+                    curve.gain(i as f32 / 30.0)
+                } else {
+                    1.0
+                };
+                let fade_out = if 39 - i < 30 {
+                    Curve::Linear.gain((39 - i) as f32 / 30.0)
+                } else {
+                    1.0
+                };
+                assert_gain(&chunk, i, fade_in * fade_out);
+            }
+            for i in 40..50 {
+                assert_gain(&chunk, i, 0.0);
+            }
+
+            // both fades apply in the middle, so it never reaches full gain
+            assert!(chunk[20] < AMPLITUDE);
+        }
 
-        let sp = tracing::trace_span!("get_chunk", len = total_len);
-        let _e = sp.enter();
+        let _ = fs::remove_file(path);
+    }
 
-        let chunk = match self.spec().sample_format {
-            hound::SampleFormat::Int => match self.spec().bits_per_sample {
-                8 => {
-                    let samples = self.wav_reader.samples();
-                    samples
-                        .take(len)
-                        .map(|v| v.map(i8::to_sample))
-                        .enumerate()
-                        .map(Self::fade(
-                            spec,
-                            *self.reader_position,
-                            total_len,
-                            self.fade_in,
-                            self.fade_out,
-                        ))
-                        .collect::<Result<Vec<f32>, hound::Error>>()
-                        .context(DecodeError)
-                }
-                16 => {
-                    let samples = self.wav_reader.samples();
-                    samples
-                        .take(len)
-                        .map(|v| v.map(i16::to_sample))
-                        .enumerate()
-                        .map(Self::fade(
-                            spec,
-                            *self.reader_position,
-                            total_len,
-                            self.fade_in,
-                            self.fade_out,
-                        ))
-                        .collect::<Result<Vec<f32>, hound::Error>>()
-                        .context(DecodeError)
-                }
-                24 => {
-                    let samples = self.wav_reader.samples::<i32>();
-                    samples
-                        .take(len)
-                        .map(|v| v.map(I24::new_unchecked).map(I24::to_sample))
-                        .enumerate()
-                        .map(Self::fade(
-                            spec,
-                            *self.reader_position,
-                            total_len,
-                            self.fade_in,
-                            self.fade_out,
-                        ))
-                        .collect::<Result<Vec<f32>, hound::Error>>()
-                        .context(DecodeError)
-                }
-                v => Err(ReadError::UnsupportedDepth { depth: v }),
-            },
-            hound::SampleFormat::Float => match self.spec().bits_per_sample {
-                32 => {
-                    let samples = self.wav_reader.samples();
-                    samples
-                        .take(len)
-                        .enumerate()
-                        .map(Self::fade(
-                            spec,
-                            *self.reader_position,
-                            total_len,
-                            self.fade_in,
-                            self.fade_out,
-                        ))
-                        .collect::<Result<Vec<f32>, hound::Error>>()
-                        .context(DecodeError)
-                }
-                v => Err(ReadError::UnsupportedDepth { depth: v }),
-            },
-        };
+    #[test]
+    fn overview_is_computed_on_request() {
+        let path = write_wav("overview", 100);
+        let mut source = AudioSource::new(path.clone());
+        source.load().unwrap();
+        assert!(matches!(source.overview, OverviewState::Unrequested));
+
+        source.request_overview();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while source.as_loaded().unwrap().overview.is_none() {
+            assert!(Instant::now() < deadline, "overview never arrived");
+            thread::sleep(Duration::from_millis(5));
+        }
 
-        *self.reader_position += len as u32;
+        let _ = fs::remove_file(path);
+    }
 
-        chunk
+    #[test]
+    fn curves_span_silence_to_unity() {
+        for &curve in Curve::ALL.iter() {
+            assert_eq!(curve.gain(0.0), 0.0);
+            assert!((curve.gain(1.0) - 1.0).abs() < 1e-6);
+            assert_eq!(curve.gain(-1.0), 0.0);
+            assert!((curve.gain(2.0) - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(Curve::Linear.gain(0.25), 0.25);
     }
 }
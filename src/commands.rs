@@ -1,2 +1,3 @@
 pub mod app;
 pub mod configure_audio;
+pub mod new;
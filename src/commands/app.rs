@@ -1,4 +1,5 @@
 use std::panic::{set_hook, take_hook};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::{io, thread, time};
 
@@ -14,11 +15,13 @@ use winit::{
 };
 
 use crate::audio::{
+    capture,
     connection::{ConnectionTarget, MasterChannel},
     mixer, playback,
 };
 use crate::config;
 use crate::panic;
+use crate::recovery;
 use crate::state::{self, State};
 use crate::ui;
 
@@ -121,18 +124,22 @@ enum Error {
 
     #[snafu(display("Failed to create master audio player: {}", source))]
     MasterCreation { source: playback::CreateError },
+
+    #[snafu(display("Failed to open audio input: {}", source))]
+    CaptureCreation { source: capture::CreateError },
 }
 
-fn load_state(state_file: Option<&str>) -> state::State {
+fn load_state(state_file: Option<&str>, config: &mut config::Config) -> state::State {
     let sp = tracing::debug_span!("load_project", path = ?state_file);
     let _e = sp.enter();
 
     match state_file {
         Some(path) => match State::from_file(path) {
-            Ok((state, warnings)) => {
+            Ok((mut state, warnings)) => {
                 for w in warnings {
-                    tracing::warn!("{}", w);
+                    state.ui.messages.warn(w.to_string());
                 }
+                config.add_recent_project(&state.file_path);
                 state
             }
             Err(state::ReadError::OpenError { ref source, .. })
@@ -142,21 +149,37 @@ fn load_state(state_file: Option<&str>) -> state::State {
                 let sp = tracing::debug_span!("write_default");
                 let _e = sp.enter();
 
-                let state = State::default();
-                if let Err(e) = state.write(path) {
-                    tracing::warn!("{}", e);
+                let mut state = State::default();
+                match state.write(path) {
+                    Ok(()) => {
+                        state.file_path = Path::new(path)
+                            .canonicalize()
+                            .unwrap_or_else(|_| path.into());
+                        config.add_recent_project(&state.file_path);
+                    }
+                    Err(e) => tracing::warn!("{}", e),
                 }
                 state
             }
             Err(e) => {
-                tracing::error!("{}", e);
-                State::default()
+                let mut state = State::default();
+                state.ui.messages.error(e.to_string());
+                state
             }
         },
         None => State::default(),
     }
 }
 
+fn window_title(state: &State) -> String {
+    let name = state
+        .file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "untitled".to_string());
+    format!("{}{} - rawrscope", name, if state.dirty { "*" } else { "" })
+}
+
 fn preview_transform(window_res: (u32, u32), scope_res: (u32, u32)) -> uv::Mat4 {
     let window_ratio = window_res.0 as f32 / window_res.1 as f32;
     let scope_ratio = scope_res.0 as f32 / scope_res.1 as f32;
@@ -170,6 +193,20 @@ fn preview_transform(window_res: (u32, u32), scope_res: (u32, u32)) -> uv::Mat4
     }
 }
 
+// plays samples back slower by interpolating between them, like slowing down
+// a tape
+fn varispeed(samples: Vec<f32>, speed: f32) -> impl Iterator<Item = f32> {
+    let len = samples.len();
+    (0u32..)
+        .map(move |i| i as f32 * speed)
+        .take_while(move |pos| (*pos as usize) + 1 < len)
+        .map(move |pos| {
+            let i = pos as usize;
+            let t = pos - i as f32;
+            samples[i] * (1.0 - t) + samples[i + 1] * t
+        })
+}
+
 fn rebuild_master(
     master: &mut playback::Player,
     state: &mut State,
@@ -196,23 +233,143 @@ fn rebuild_master(
     master.rebuild_mixer(mixer_config)
 }
 
-fn _run(state_file: Option<&str>) -> Result<(), Error> {
+// feeds the most recently captured audio to the scopes it is routed to
+/// Adds the files of one drop along with their scopes, which are placed
+/// together. Returns whether anything was added.
+fn add_dropped_files(state: &mut State, paths: Vec<PathBuf>) -> bool {
+    let mut names = Vec::new();
+    for path in paths {
+        let sp = tracing::debug_span!("dropped_file", path = ?path);
+        let _e = sp.enter();
+
+        let source = crate::audio::source::AudioSource::new(path);
+        match state.add_source_with_scopes(source, true) {
+            Ok(added) => names.extend(added),
+            Err(e) => state.ui.messages.warn(e.to_string()),
+        }
+    }
+
+    if names.is_empty() {
+        return false;
+    }
+
+    state.mark_changed();
+    state.place_scopes(&names);
+    state.configure_mixers();
+    true
+}
+
+fn submit_live_input(state: &mut State, capture: &capture::Capture) {
+    let channels = capture.channels();
+    let sample_rate = capture.sample_rate();
+
+    // e.g. after opening another project
+    if state.live_input.as_ref().and_then(|l| l.sample_rate) != Some(sample_rate) {
+        state.start_live_input(channels, sample_rate);
+    }
+    let live = match &state.live_input {
+        Some(live) => live,
+        None => return,
+    };
+
+    let window_secs = state
+        .scopes
+        .iter()
+        .map(|(_, s)| s.wanted_length())
+        .max_by(|a, b| a.partial_cmp(b).unwrap()) // time shouldnt be NaN
+        .unwrap_or(0.0);
+    let window = capture.latest((sample_rate as f32 * window_secs) as usize);
+
+    for (name, scope) in state.scopes.iter_mut() {
+        let sub_len = (sample_rate as f32 * scope.wanted_length()) as usize;
+        let mut sub = scope.build_submission();
+
+        for conn in live.connections.iter() {
+            let filter = match &conn.target {
+                ConnectionTarget::Scope { filter, .. } if conn.target.targets_scope(name) => {
+                    *filter
+                }
+                _ => continue,
+            };
+
+            let mut samples = window
+                .iter()
+                .skip(conn.channel as usize)
+                .step_by(channels as usize)
+                .copied()
+                .collect::<Vec<_>>();
+            if let Some(filter) = filter {
+                filter.apply(&mut samples, sample_rate);
+            }
+
+            let offset = samples.len().saturating_sub(sub_len);
+            sub.add(sample_rate, 0, samples.into_iter().skip(offset));
+        }
+
+        scope.submit(sub);
+    }
+}
+
+// centers and renders all scopes with their submitted audio
+fn render_scopes(
+    state: &mut State,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scope_renderer: &mut crate::render::Renderer,
+) -> wgpu::CommandBuffer {
+    // TODO add logging spans per scope for per-scope logging
+    let sp = tracing::debug_span!("centering");
+    let centering_entered = sp.enter();
+    if state.debug.multithreaded_centering {
+        state
+            .scopes
+            .values_mut()
+            .par_bridge()
+            .for_each(|scope| scope.process());
+    } else {
+        state
+            .scopes
+            .iter_mut()
+            .for_each(|(_, scope)| scope.process());
+    }
+    drop(centering_entered);
+
+    let mut encoder: wgpu::CommandEncoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("scope render"),
+        });
+    scope_renderer.render(device, queue, &mut encoder, state);
+    encoder.finish()
+}
+
+fn _run(state_file: Option<&str>, live: bool) -> Result<(), Error> {
     let sp = tracing::info_span!("init");
     let init_entered = sp.enter();
 
     set_hook(panic::dialog(take_hook()));
 
     // load config
-    let config = config::Config::load();
-    let mut state = load_state(state_file);
+    let mut config = config::Config::load();
+    let shortcuts = ui::shortcuts::Shortcuts::new(&config.keybindings);
+    // a project given on the command line is opened as is
+    let restored = match state_file {
+        Some(_) => None,
+        None => recovery::restore_previous_session(),
+    };
+    let mut state = match restored {
+        Some(state) => state,
+        None => load_state(state_file, &mut config),
+    };
+    recovery::begin_session(&state);
 
     // create window
     let sp = tracing::debug_span!("window");
     let win_entered = sp.enter();
     let event_loop = EventLoop::<(usize, wgpu::SwapChainFrame)>::with_user_event();
+    let mut current_title = window_title(&state);
     let window = WindowBuilder::new()
         .with_inner_size(winit::dpi::PhysicalSize::new(1600.0, 900.0))
-        .with_title("rawrscope")
+        .with_title(&current_title)
         .with_resizable(true)
         .build(&event_loop)
         .context(WindowCreation)?;
@@ -264,6 +421,13 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
     if let Err(e) = rebuild_master(&mut master, &mut state) {
         tracing::error!("{}", e);
     }
+    let capture = if live {
+        let capture = capture::Capture::new(&config).context(CaptureCreation)?;
+        state.start_live_input(capture.channels(), capture.sample_rate());
+        Some(capture)
+    } else {
+        None
+    };
     drop(audio_init_entered);
 
     // initialize imgui
@@ -302,15 +466,24 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
     );
     drop(renderers_init_entered);
 
-    let buffer_duration = time::Duration::from_secs_f32(config.audio.buffer_ms / 1000.0);
+    let mut buffer_duration = time::Duration::from_secs_f32(config.audio.buffer_ms / 1000.0);
 
-    let scope_frame_secs = 1.0 / state.appearance.framerate as f32;
-    let scope_frame_duration = time::Duration::from_secs_f32(scope_frame_secs);
     let mut scope_timer = time::Instant::now() - buffer_duration;
 
     let mut frame_timer = time::Instant::now();
+    let mut ticks: u64 = 0;
+
+    let reload_interval = time::Duration::from_secs(1);
+    let mut reload_timer = time::Instant::now();
+
+    let autosave_interval = time::Duration::from_secs(config.files.autosave_secs);
+    let mut autosave_timer = time::Instant::now();
+    let mut heartbeat_timer = time::Instant::now();
+    let mut emergency_generation = None;
 
     let mut reprocess = true;
+    // winit sends one event per file
+    let mut dropped_files = Vec::new();
     let mut command_buffers: Vec<wgpu::CommandBuffer> = Vec::new();
 
     drop(init_entered);
@@ -331,7 +504,10 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
             event::Event::WindowEvent { event, .. } => match event {
                 event::WindowEvent::CloseRequested => {
                     tracing::debug!("Exit requested");
-                    *control_flow = ControlFlow::Exit;
+                    if ui::confirm_discard(&state) {
+                        recovery::end_session();
+                        *control_flow = ControlFlow::Exit;
+                    }
                 }
                 event::WindowEvent::Resized(size) => {
                     let sp = tracing::debug_span!("resize", size = ?size);
@@ -350,6 +526,7 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                         preview_transform(size.into(), (1920, 1080)),
                     );
                 }
+                event::WindowEvent::DroppedFile(path) => dropped_files.push(path),
                 event::WindowEvent::MouseInput { .. }
                 | event::WindowEvent::CursorMoved { .. }
                 | event::WindowEvent::KeyboardInput { .. }
@@ -359,6 +536,15 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                 }
                 _ => {}
             },
+            event::Event::MainEventsCleared if !dropped_files.is_empty() => {
+                if add_dropped_files(&mut state, dropped_files.split_off(0)) {
+                    if let Err(e) = rebuild_master(&mut master, &mut state) {
+                        tracing::warn!("Failed to rebuild master mixer: {}", e);
+                    }
+                    reprocess = true;
+                }
+                window.request_redraw();
+            }
             event::Event::RedrawRequested(_) => {
                 tracing::trace!("Requesting new swapchain image");
                 swapchain.request_image();
@@ -383,9 +569,35 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
 
                 let im_ui = imgui.frame();
                 let mut ext_events = ui::ExternalEvents::default();
-                ui::ui(&mut state, &im_ui, &mut ext_events);
+                ui::ui(&mut state, &mut config, &shortcuts, &im_ui, &mut ext_events);
 
                 // process external events
+                if ext_events.contains(ui::ExternalEvents::REBUILD_PLAYER) {
+                    let sp = tracing::debug_span!("rebuild_player");
+                    let _e = sp.enter();
+
+                    // the old output has to be closed before the same device
+                    // can be opened again
+                    master.close();
+                    let player = playback::Player::new(&config).or_else(|e| {
+                        state.ui.messages.error(format!(
+                            "Failed to open audio output, audio is disabled: {}",
+                            e
+                        ));
+                        let mut fallback = config.clone();
+                        fallback.audio.backend = config::OutputBackend::Null;
+                        playback::Player::new(&fallback)
+                    });
+                    match player {
+                        Ok(player) => {
+                            master = player;
+                            buffer_duration =
+                                time::Duration::from_secs_f32(config.audio.buffer_ms / 1000.0);
+                            ext_events |= ui::ExternalEvents::REBUILD_MASTER;
+                        }
+                        Err(e) => tracing::error!("Failed to create null audio output: {}", e),
+                    }
+                }
                 if ext_events.contains(ui::ExternalEvents::REBUILD_MASTER) {
                     if let Err(e) = rebuild_master(&mut master, &mut state) {
                         tracing::warn!("Failed to rebuild master mixer: {}", e);
@@ -394,6 +606,18 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                 if ext_events.contains(ui::ExternalEvents::REDRAW_SCOPES) {
                     reprocess = true;
                 }
+
+                let generation = (state.history.generation(), state.dirty);
+                if emergency_generation != Some(generation) {
+                    emergency_generation = Some(generation);
+                    recovery::update_emergency_snapshot(&state);
+                }
+
+                let title = window_title(&state);
+                if title != current_title {
+                    window.set_title(&title);
+                    current_title = title;
+                }
                 drop(ui_entered);
 
                 // begin rendering
@@ -455,12 +679,64 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
 
                 let now = time::Instant::now();
 
-                // create audio submission
-                let sub_builder = master.submission_builder(); // TODO optimize
-                let mut sub = sub_builder.create(scope_frame_secs);
+                if config.files.autosave_secs > 0
+                    && state.dirty
+                    && now.saturating_duration_since(autosave_timer) > autosave_interval
+                {
+                    autosave_timer = now;
+                    recovery::autosave(&mut state);
+                }
+
+                // other instances only offer to recover sessions that stopped
+                if now.saturating_duration_since(heartbeat_timer) > recovery::HEARTBEAT {
+                    heartbeat_timer = now;
+                    recovery::keep_alive(&state);
+                }
 
-                let f = state.playback.frame;
+                // pick up re-rendered audio files
+                if now.saturating_duration_since(reload_timer) > reload_interval {
+                    reload_timer = now;
+                    if state.reload_changed_sources() {
+                        if let Err(e) = rebuild_master(&mut master, &mut state) {
+                            tracing::warn!("Failed to rebuild master mixer: {}", e);
+                        }
+                        reprocess = true;
+                    }
+                }
+
+                // audio queued from before a seek would move the picture back
+                if std::mem::take(&mut state.playback.seeked) {
+                    master.clear_markers();
+                }
+
+                // follow the audio that is actually being heard
+                if state.playback.playing {
+                    let offset = config.audio.latency_offset_ms / 1000.0;
+                    if let Some(position) = master.heard_position(offset) {
+                        state.playback.set_position(position);
+                    }
+                } else {
+                    state.playback.write_position = state.playback.position();
+                    master.clear_markers();
+                }
+
+                // create audio submission
                 let framerate = state.appearance.framerate;
+                let frame_secs = framerate.frame_secs() as f32;
+                let speed = state.playback.speed;
+
+                // the number of samples in a frame varies if the rates don't
+                // divide evenly, sizing submissions after the frame that is
+                // written keeps master audio contiguous
+                let sub_frame = if state.playback.playing {
+                    state.playback.write_position.floor() as u64
+                } else {
+                    ticks
+                };
+                let sub_builder = master.submission_builder(); // TODO optimize
+                let mut sub = sub_builder.create_exact(frame_secs, |rate| {
+                    framerate.samples_in_frame(sub_frame, rate) as usize
+                });
 
                 let mut loaded_sources = state
                     .audio_sources
@@ -468,13 +744,87 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                     .filter_map(|s| s.as_loaded())
                     .collect::<Vec<_>>();
 
-                // TODO this is scuffed
-                let sources_exhausted = loaded_sources
-                    .iter()
-                    .all(|source| f > source.len() / (source.spec().sample_rate / framerate));
+                let playback = &state.playback;
+                let sources_exhausted = loaded_sources.iter().all(|source| {
+                    let playhead = playback.write_playhead(source.spec().sample_rate, framerate);
+                    i64::from(playhead) >= source.timeline_end()
+                });
+
+                // don't let master audio run too far ahead of the device, e.g.
+                // if it plays slower than our timer or after a stall
+                let max_queued = f64::from(master.sample_rate())
+                    * (buffer_duration.as_secs_f64() + 2.0 * framerate.frame_secs());
+                let queue_full = master.queued_frames() as f64 > max_queued;
+
+                // read master audio at the write position
+                let submit_master = (state.playback.playing && !sources_exhausted)
+                    || (state.playback.scrubbing && reprocess);
+                if submit_master && !queue_full {
+                    let sp = tracing::trace_span!("master");
+                    let _e = sp.enter();
+
+                    for source in &mut loaded_sources {
+                        if !source.connections.iter().any(|c| c.target.is_master()) {
+                            continue;
+                        }
+
+                        let channels = source.spec().channels;
+                        let sample_rate = source.spec().sample_rate;
+
+                        // one extra frame for interpolation when slowed down
+                        let frame_len = framerate.samples_in_frame(sub_frame, sample_rate);
+                        let len = (frame_len as f32 * speed) as usize + 1;
+                        let playhead = state.playback.write_playhead(sample_rate, framerate);
+                        let chunk = match source.chunk_at(playhead, len * channels as usize) {
+                            Ok(c) => c,
+                            Err(e) => {
+                                tracing::warn!("Failed to read audio: {}", e);
+                                continue;
+                            }
+                        };
+
+                        for conn in source.connections {
+                            let master_channel = match conn.target {
+                                ConnectionTarget::Master { ref channel } => match channel {
+                                    MasterChannel::Left => 0,
+                                    MasterChannel::Right => 1,
+                                },
+                                _ => continue,
+                            };
+
+                            let samples = chunk
+                                .iter()
+                                .skip(conn.channel as usize)
+                                .step_by(channels as usize)
+                                .copied();
+                            if (speed - 1.0).abs() < f32::EPSILON {
+                                sub.add(sample_rate, master_channel, samples);
+                            } else {
+                                sub.add(
+                                    sample_rate,
+                                    master_channel,
+                                    varispeed(samples.collect(), speed),
+                                );
+                            }
+                        }
+                    }
+                }
+
+                // live input has no playhead, always show the latest audio
+                if let Some(capture) = &capture {
+                    reprocess = false;
+                    submit_live_input(&mut state, capture);
+                    command_buffers.push(render_scopes(
+                        &mut state,
+                        &device,
+                        &queue,
+                        &mut scope_renderer,
+                    ));
 
-                // process any pending audio
-                if !sources_exhausted && state.playback.playing || reprocess {
+                    tracing::trace!("Submitting winit redraw request");
+                    window.request_redraw();
+                } else if state.playback.playing || reprocess {
+                    // process any pending audio
                     reprocess = false;
                     // create scope submissions
                     let mut scope_submissions = state
@@ -494,8 +844,6 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                         .map(|(_, s)| s.wanted_length())
                         .max_by(|a, b| a.partial_cmp(b).unwrap()) // time shouldnt be NaN
                         .unwrap_or(0.0);
-                    let full_window_secs =
-                        scope_window_secs.max(scope_frame_secs + scope_window_secs / 2.);
 
                     for source in &mut loaded_sources {
                         let sp =
@@ -505,20 +853,21 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                         let channels = source.spec().channels;
                         let sample_rate = source.spec().sample_rate;
 
-                        let scope_window_len =
-                            (sample_rate as f32 * scope_window_secs * f32::from(channels)) as u32;
-                        let full_window_len =
-                            (sample_rate as f32 * full_window_secs * f32::from(channels)) as u32;
+                        // window lengths are in frames
+                        let scope_window_len = (sample_rate as f32 * scope_window_secs) as u32;
 
-                        let playhead = (sample_rate / framerate) * state.playback.frame;
+                        let playhead = state.playback.playhead(sample_rate, framerate);
                         let window_pos = playhead.saturating_sub(scope_window_len / 2);
 
-                        let window = source
-                            .chunk_at(window_pos, full_window_len as usize)
-                            .unwrap() // safe - no sources should be exhausted
-                            .iter()
-                            .copied()
-                            .collect::<Vec<_>>();
+                        let window = match source
+                            .chunk_at(window_pos, scope_window_len as usize * channels as usize)
+                        {
+                            Ok(w) => w,
+                            Err(e) => {
+                                tracing::warn!("Failed to read audio: {}", e);
+                                continue;
+                            }
+                        };
 
                         for conn in source.connections {
                             tracing::trace!(conn = ?conn, "Connecting source");
@@ -528,23 +877,16 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                                 .skip(conn.channel as usize)
                                 .step_by(channels as usize)
                                 .copied();
-                            let playhead_offset = (playhead - window_pos) / channels as u32;
+                            let playhead_offset = playhead - window_pos;
 
                             match conn.target {
-                                ConnectionTarget::Master { ref channel } => {
-                                    // only submit master when playing
-                                    if state.playback.playing {
-                                        sub.add(
-                                            sample_rate,
-                                            match channel {
-                                                MasterChannel::Left => 0,
-                                                MasterChannel::Right => 1,
-                                            },
-                                            channel_iter.skip(playhead_offset as usize),
-                                        );
-                                    }
-                                }
-                                ConnectionTarget::Scope { ref name, channel } => {
+                                // submitted separately, ahead of the picture
+                                ConnectionTarget::Master { .. } => {}
+                                ConnectionTarget::Scope {
+                                    ref name,
+                                    channel,
+                                    filter,
+                                } => {
                                     if channel != 0 {
                                         tracing::warn!("Scope channels unimplemented");
                                     }
@@ -554,7 +896,17 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                                         let sub_len = (sample_rate as f32 * *wanted_length) as u32;
                                         let offset = playhead_offset.saturating_sub(sub_len / 2);
 
-                                        sub.add(sample_rate, 0, channel_iter.skip(offset as usize));
+                                        // filter the whole window to give the filter time to settle
+                                        let mut samples = channel_iter.collect::<Vec<_>>();
+                                        if let Some(filter) = filter {
+                                            filter.apply(&mut samples, sample_rate);
+                                        }
+
+                                        sub.add(
+                                            sample_rate,
+                                            0,
+                                            samples.into_iter().skip(offset as usize),
+                                        );
                                     } else {
                                         tracing::warn!(target = %name, "Unknown connection target");
                                     }
@@ -569,52 +921,48 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
                         state.scopes.get_mut(&name).unwrap().submit(sub);
                     }
 
-                    // TODO add logging spans per scope for per-scope logging
-                    let sp = tracing::debug_span!("centering");
-                    let centering_entered = sp.enter();
-                    if state.debug.multithreaded_centering {
-                        state
-                            .scopes
-                            .values_mut()
-                            .par_bridge()
-                            .for_each(|scope| scope.process());
-                    } else {
-                        state
-                            .scopes
-                            .iter_mut()
-                            .for_each(|(_, scope)| scope.process());
-                    }
-                    drop(centering_entered);
-
-                    // render scopes
-                    let mut encoder: wgpu::CommandEncoder =
-                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                            label: Some("scope render"),
-                        });
-                    scope_renderer.render(&device, &queue, &mut encoder, &state);
-                    command_buffers.push(encoder.finish());
+                    command_buffers.push(render_scopes(
+                        &mut state,
+                        &device,
+                        &queue,
+                        &mut scope_renderer,
+                    ));
 
                     tracing::trace!("Submitting winit redraw request");
                     window.request_redraw();
                 }
 
                 // pause when done
-                if sources_exhausted && state.playback.playing {
+                if sources_exhausted && state.playback.playing && !state.playback.looping {
                     state.playback.playing = false;
                 }
 
                 // submit master audio
-                tracing::trace!("Submitting master audio");
-                if let Err(e) = master.submit(sub) {
-                    tracing::error!("Failed to submit audio to master: {}", e);
-                }
+                if !queue_full {
+                    tracing::trace!("Submitting master audio");
+                    let position = if state.playback.playing && submit_master {
+                        Some((state.playback.write_position, f64::from(speed)))
+                    } else {
+                        None
+                    };
+                    let frames = framerate.samples_in_frame(sub_frame, master.sample_rate());
+                    if let Err(e) = master.submit(sub, frames, position) {
+                        tracing::error!("Failed to submit audio to master: {}", e);
+                    }
 
-                if state.playback.playing {
-                    state.playback.frame += 1;
+                    if state.playback.playing {
+                        state.playback.advance();
+
+                        // the next submission starts reading at the loop start
+                        if let Some(start) = state.playback.loop_restart(sources_exhausted) {
+                            state.playback.write_position = f64::from(start);
+                        }
+                    }
                 }
 
                 // update scope timer
-                scope_timer += scope_frame_duration;
+                ticks += 1;
+                scope_timer += framerate.frame_duration();
                 if now.saturating_duration_since(scope_timer) > buffer_duration {
                     scope_timer = now - buffer_duration;
                 }
@@ -624,8 +972,92 @@ fn _run(state_file: Option<&str>) -> Result<(), Error> {
     });
 }
 
-pub fn run(state_file: Option<&str>) {
-    if let Err(e) = _run(state_file) {
+pub fn run(state_file: Option<&str>, live: bool) {
+    if let Err(e) = _run(state_file, live) {
         tracing::error!("{}", e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn live_input_reaches_its_scopes() {
+        let samples = (0..100).flat_map(|_| vec![0.5, -0.5]);
+        let path = test_util::write_wav("live", 2, 1000, samples);
+
+        let mut config = config::Config::default();
+        config.audio.input_file = Some(path.clone());
+        let capture = capture::Capture::new(&config).unwrap();
+
+        // more than the longest scope window
+        let mut probe = State::default();
+        probe.start_live_input(2, 1000);
+        let window_secs = probe
+            .scopes
+            .values()
+            .map(|s| s.wanted_length())
+            .fold(0.0, f32::max);
+        capture.wait_for((1000.0 * window_secs) as usize + 1);
+
+        let mut state = State::default();
+        submit_live_input(&mut state, &capture);
+
+        assert!(state.dirty);
+        assert_eq!(
+            state.live_input.as_ref().and_then(|l| l.sample_rate),
+            Some(1000)
+        );
+        let names = state.scopes.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(names, ["Input 1", "Input 2"]);
+
+        for (name, expected) in names.iter().zip(&[0.5, -0.5]) {
+            let scope = state.scopes.get_mut(name).unwrap();
+            scope.process();
+            assert!(
+                scope.output().iter().all(|s| s == expected),
+                "{} doesn't show its channel",
+                name
+            );
+        }
+
+        // the routing is kept from now on
+        submit_live_input(&mut state, &capture);
+        assert_eq!(state.scopes.len(), 2);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn dropped_files_fill_a_square_grid() {
+        let paths = (0..5)
+            .map(|i| test_util::write_wav(&format!("drop-{}", i), 1, 1000, vec![0.5; 10]))
+            .collect::<Vec<_>>();
+        let rects = |state: &State| {
+            state
+                .scopes
+                .values()
+                .map(|s| (s.rect.x, s.rect.y))
+                .collect::<Vec<_>>()
+        };
+
+        let mut state = State::default();
+        assert!(add_dropped_files(&mut state, paths[..4].to_vec()));
+        assert!(state.dirty);
+        assert_eq!(state.appearance.grid_columns, 2);
+        assert_eq!(state.appearance.grid_rows, 2);
+        assert_eq!(rects(&state), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        // a later drop keeps the others in place and grows the grid sideways
+        assert!(add_dropped_files(&mut state, paths[4..].to_vec()));
+        assert_eq!(state.appearance.grid_columns, 3);
+        assert_eq!(state.appearance.grid_rows, 2);
+        assert_eq!(rects(&state), [(0, 0), (1, 0), (0, 1), (1, 1), (2, 0)]);
+
+        for path in paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -1,68 +1,378 @@
-use std::io::{BufRead, Write};
+use std::io::{self, BufRead, Write};
 
 use cpal::traits::{DeviceTrait, HostTrait};
+use serde::Serialize;
+use snafu::{OptionExt, ResultExt, Snafu};
 
-use crate::config;
+use crate::audio::playback;
+use crate::config::{self, OutputBackend};
 
-// TODO maybe use snafu to clean up error handling
-pub fn run() {
-    let stdin = std::io::stdin();
-    let mut stdin = stdin.lock().lines();
+#[derive(Debug, Snafu)]
+enum Error {
+    #[snafu(display("Unknown audio host \"{}\", available hosts: {}", name, available))]
+    UnknownHost { name: String, available: String },
 
-    println!("Select audio host:");
+    #[snafu(display("Could not use host \"{}\": {}", name, source))]
+    HostUnavailable {
+        name: String,
+        source: cpal::HostUnavailable,
+    },
+
+    #[snafu(display("Failed to query devices: {}", source))]
+    QueryDevices { source: cpal::DevicesError },
+
+    #[snafu(display("No {} devices available on host \"{:?}\"", kind, host))]
+    NoDevices {
+        kind: &'static str,
+        host: cpal::HostId,
+    },
+
+    #[snafu(display("No {} device named \"{}\" on host \"{:?}\"", kind, name, host))]
+    UnknownDevice {
+        kind: &'static str,
+        name: String,
+        host: cpal::HostId,
+    },
+
+    #[snafu(display("Invalid buffer size \"{}\"", value))]
+    InvalidBufferSize { value: String },
+
+    #[snafu(display("Invalid sample rate \"{}\"", value))]
+    InvalidSampleRate { value: String },
+
+    #[snafu(display("Output device \"{}\" does not support {}hz", device, rate))]
+    UnsupportedSampleRate { device: String, rate: u32 },
+
+    #[snafu(display("Failed to read selection: {}", source))]
+    Stdin { source: io::Error },
+
+    #[snafu(display("No lines available from stdin!"))]
+    StdinClosed,
+
+    #[snafu(display("Failed to serialize device list: {}", source))]
+    SerializeList { source: serde_json::Error },
+
+    #[snafu(display("Failed to save config: {}", source))]
+    WriteConfig { source: config::Error },
+}
+
+#[derive(Serialize)]
+struct FormatInfo {
+    channels: u16,
+    min_sample_rate: u32,
+    max_sample_rate: u32,
+    sample_format: String,
+}
+
+#[derive(Serialize)]
+struct DeviceInfo {
+    name: String,
+    default: bool,
+    formats: Vec<FormatInfo>,
+}
+
+#[derive(Serialize)]
+struct HostInfo {
+    name: String,
+    default: bool,
+    output_devices: Vec<DeviceInfo>,
+    input_devices: Vec<DeviceInfo>,
+    /// Kept with the listing instead of logged, so that JSON output stays
+    /// parseable
+    errors: Vec<String>,
+}
+
+fn kind(input: bool) -> &'static str {
+    if input {
+        "input"
+    } else {
+        "output"
+    }
+}
+
+/// Host by case-insensitive name, or the default host
+fn open_host(name: Option<&str>) -> Result<cpal::Host, Error> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(cpal::default_host()),
+    };
 
     let hosts = cpal::available_hosts();
-    for (i, host_id) in hosts.iter().enumerate() {
-        println!("{:>3}: {:?}", i, host_id);
+    let id = hosts
+        .iter()
+        .find(|id| format!("{:?}", id).eq_ignore_ascii_case(name))
+        .context(UnknownHost {
+            name,
+            available: hosts
+                .iter()
+                .map(|id| format!("{:?}", id))
+                .collect::<Vec<_>>()
+                .join(", "),
+        })?;
+    cpal::host_from_id(*id).context(HostUnavailable { name })
+}
+
+fn devices(host: &cpal::Host, input: bool) -> Result<Vec<cpal::Device>, Error> {
+    let devices = if input {
+        host.input_devices().map(|d| d.collect())
+    } else {
+        host.output_devices().map(|d| d.collect())
+    };
+    devices.context(QueryDevices)
+}
+
+fn find_device(host: &cpal::Host, name: &str, input: bool) -> Result<cpal::Device, Error> {
+    devices(host, input)?
+        .into_iter()
+        .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+        .context(UnknownDevice {
+            kind: kind(input),
+            name,
+            host: host.id(),
+        })
+}
+
+fn device_info(
+    device: &cpal::Device,
+    default_name: Option<&str>,
+    input: bool,
+    errors: &mut Vec<String>,
+) -> DeviceInfo {
+    let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+
+    let formats = if input {
+        device.supported_input_formats().map(|f| f.collect())
+    } else {
+        device.supported_output_formats().map(|f| f.collect())
+    };
+    let formats: Vec<cpal::SupportedFormat> = formats.unwrap_or_else(|e| {
+        errors.push(format!("Failed to query formats of \"{}\": {}", name, e));
+        Vec::new()
+    });
+
+    DeviceInfo {
+        default: Some(name.as_str()) == default_name,
+        formats: formats
+            .into_iter()
+            .map(|f| FormatInfo {
+                channels: f.channels,
+                min_sample_rate: f.min_sample_rate.0,
+                max_sample_rate: f.max_sample_rate.0,
+                sample_format: format!("{:?}", f.data_type),
+            })
+            .collect(),
+        name,
     }
+}
 
-    let host_id = loop {
-        print!("> ");
-        if let Err(e) = std::io::stdout().flush() {
-            tracing::error!("{}", e);
-            return;
+fn host_info(id: cpal::HostId) -> HostInfo {
+    let mut info = HostInfo {
+        name: format!("{:?}", id),
+        default: id == cpal::default_host().id(),
+        output_devices: Vec::new(),
+        input_devices: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let host = match cpal::host_from_id(id) {
+        Ok(host) => host,
+        Err(e) => {
+            info.errors.push(e.to_string());
+            return info;
         }
+    };
 
-        let idx_str = match stdin.next() {
-            Some(Ok(s)) => s,
-            Some(Err(e)) => {
-                tracing::error!("{}", e);
-                return;
-            }
-            None => {
-                tracing::error!("No lines available from stdin!");
-                return;
+    for &input in [false, true].iter() {
+        let default_device = if input {
+            host.default_input_device()
+        } else {
+            host.default_output_device()
+        };
+        let default_name = default_device.and_then(|d| d.name().ok());
+
+        let infos = match devices(&host, input) {
+            Ok(devices) => devices
+                .iter()
+                .map(|d| device_info(d, default_name.as_deref(), input, &mut info.errors))
+                .collect(),
+            Err(e) => {
+                info.errors.push(e.to_string());
+                Vec::new()
             }
         };
+        if input {
+            info.input_devices = infos;
+        } else {
+            info.output_devices = infos;
+        }
+    }
 
-        if let Ok(idx) = idx_str.parse::<usize>() {
-            if let Some(host) = hosts.get(idx) {
-                break host;
+    info
+}
+
+fn list(json: bool) -> Result<(), Error> {
+    let hosts = cpal::available_hosts()
+        .into_iter()
+        .map(host_info)
+        .collect::<Vec<_>>();
+
+    if json {
+        let text = serde_json::to_string_pretty(&hosts).context(SerializeList)?;
+        println!("{}", text);
+        return Ok(());
+    }
+
+    let default = |d: bool| if d { " (default)" } else { "" };
+    for host in &hosts {
+        println!("{}{}", host.name, default(host.default));
+        for e in &host.errors {
+            println!("  error: {}", e);
+        }
+        for (label, host_devices) in [
+            ("output", &host.output_devices),
+            ("input", &host.input_devices),
+        ]
+        .iter()
+        {
+            println!("  {} devices:", label);
+            for device in host_devices.iter() {
+                println!("    {}{}", device.name, default(device.default));
+                for f in &device.formats {
+                    let rates = if f.min_sample_rate == f.max_sample_rate {
+                        format!("{}", f.min_sample_rate)
+                    } else {
+                        format!("{}-{}", f.min_sample_rate, f.max_sample_rate)
+                    };
+                    println!("      {} ch, {} Hz, {}", f.channels, rates, f.sample_format);
+                }
             }
         }
+    }
+
+    Ok(())
+}
+
+// applies the selection given on the command line
+fn configure(matches: &clap::ArgMatches, input: bool) -> Result<(), Error> {
+    let mut config = config::Config::load();
+    let (mut host_name, mut device_name) = if input {
+        (
+            config.audio.input_host.clone(),
+            config.audio.input_device.clone(),
+        )
+    } else {
+        (config.audio.host.clone(), config.audio.device.clone())
     };
 
-    let host = match cpal::host_from_id(*host_id) {
-        Ok(host) => host,
-        Err(e) => {
-            tracing::error!("{}", e);
-            return;
+    if let Some(name) = matches.value_of("HOST") {
+        let host = open_host(Some(name))?;
+        host_name = Some(format!("{:?}", host.id()));
+        // device names differ between hosts
+        device_name = None;
+    }
+
+    match matches.value_of("DEVICE") {
+        Some("default") => device_name = None,
+        Some(name) => {
+            let host = open_host(host_name.as_deref())?;
+            find_device(&host, name, input)?;
+            device_name = Some(name.to_string());
         }
-    };
+        None => {}
+    }
 
-    println!("Select audio output device:");
+    if let Some(value) = matches.value_of("BUFFER_MS") {
+        config.audio.buffer_ms = value
+            .parse::<f32>()
+            .ok()
+            .filter(|ms| *ms > 0.0 && ms.is_finite())
+            .context(InvalidBufferSize { value })?;
+    }
 
-    let device_iter = match host.output_devices() {
-        Ok(iter) => iter,
-        Err(e) => {
-            tracing::error!("{}", e);
-            return;
+    match matches.value_of("SAMPLE_RATE") {
+        Some("default") => config.audio.sample_rate = None,
+        Some(value) => {
+            let rate = value
+                .parse::<u32>()
+                .ok()
+                .filter(|rate| *rate > 0)
+                .context(InvalidSampleRate { value })?;
+
+            // the null and file backends take any rate
+            if config.audio.backend == OutputBackend::Device {
+                let host = open_host(host_name.as_deref())?;
+                let device = match &device_name {
+                    Some(name) => find_device(&host, name, false)?,
+                    None => host.default_output_device().context(NoDevices {
+                        kind: "output",
+                        host: host.id(),
+                    })?,
+                };
+                if !playback::supports_sample_rate(&device, rate) {
+                    return Err(Error::UnsupportedSampleRate {
+                        device: device.name().unwrap_or_default(),
+                        rate,
+                    });
+                }
+            }
+            config.audio.sample_rate = Some(rate);
         }
-    };
-    let devices = device_iter.collect::<Vec<_>>();
+        None => {}
+    }
+
+    if input {
+        config.audio.input_host = host_name;
+        config.audio.input_device = device_name;
+    } else {
+        config.audio.host = host_name;
+        config.audio.device = device_name;
+    }
+
+    config.write().context(WriteConfig)
+}
+
+// reads lines until one is a valid index below `len`
+fn select<I: Iterator<Item = io::Result<String>>>(
+    stdin: &mut I,
+    len: usize,
+) -> Result<usize, Error> {
+    loop {
+        print!("> ");
+        io::stdout().flush().context(Stdin)?;
+
+        let idx_str = stdin.next().context(StdinClosed)?.context(Stdin)?;
+        if let Ok(idx) = idx_str.trim().parse::<usize>() {
+            if idx < len {
+                return Ok(idx);
+            }
+        }
+    }
+}
+
+fn prompt(input: bool) -> Result<(), Error> {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock().lines();
+
+    println!("Select audio host:");
+
+    let hosts = cpal::available_hosts();
+    for (i, host_id) in hosts.iter().enumerate() {
+        println!("{:>3}: {:?}", i, host_id);
+    }
+
+    let host_id = hosts[select(&mut stdin, hosts.len())?];
+    let host = cpal::host_from_id(host_id).context(HostUnavailable {
+        name: format!("{:?}", host_id),
+    })?;
+
+    println!("Select audio {} device:", kind(input));
+
+    let devices = devices(&host, input)?;
     if devices.is_empty() {
-        tracing::error!("No available output devices!");
-        return;
+        return Err(Error::NoDevices {
+            kind: kind(input),
+            host: host_id,
+        });
     }
 
     for (i, device) in devices.iter().enumerate() {
@@ -72,37 +382,38 @@ pub fn run() {
         }
     }
 
-    let device = loop {
-        print!("> ");
-        if let Err(e) = std::io::stdout().flush() {
-            tracing::error!("{}", e);
-            return;
-        }
+    let device = &devices[select(&mut stdin, devices.len())?];
 
-        let idx_str = match stdin.next() {
-            Some(Ok(s)) => s,
-            Some(Err(e)) => {
-                tracing::error!("{}", e);
-                return;
-            }
-            None => {
-                tracing::error!("No lines available from stdin!");
-                return;
-            }
-        };
+    let mut config = config::Config::load();
+    if input {
+        config.audio.input_host = Some(format!("{:?}", host_id));
+        config.audio.input_device = device.name().ok();
+    } else {
+        config.audio.host = Some(format!("{:?}", host_id));
+        config.audio.device = device.name().ok();
+    }
 
-        if let Ok(idx) = idx_str.parse::<usize>() {
-            if let Some(device) = devices.get(idx) {
-                break device;
-            }
-        }
-    };
+    config.write().context(WriteConfig)
+}
 
-    let mut config = config::Config::load();
-    config.audio.host = Some(format!("{:?}", host_id));
-    config.audio.device = device.name().ok();
+fn _run(matches: &clap::ArgMatches) -> Result<(), Error> {
+    let input = matches.is_present("INPUT");
+
+    if matches.is_present("LIST") {
+        list(matches.value_of("FORMAT") == Some("json"))
+    } else if ["HOST", "DEVICE", "BUFFER_MS", "SAMPLE_RATE"]
+        .iter()
+        .any(|arg| matches.is_present(arg))
+    {
+        configure(matches, input)
+    } else {
+        prompt(input)
+    }
+}
 
-    if let Err(e) = config.write() {
+pub fn run(matches: &clap::ArgMatches) {
+    if let Err(e) = _run(matches) {
         tracing::error!("{}", e);
+        std::process::exit(1);
     }
 }
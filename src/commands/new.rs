@@ -0,0 +1,172 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use crate::audio::{
+    connection::{Connection, ConnectionTarget, MasterChannel},
+    source::AudioSource,
+};
+use crate::scope::centering::Centering;
+use crate::state::{self, State};
+
+#[derive(Debug, Snafu)]
+enum Error {
+    #[snafu(display("Failed to read directory {}: {}", path.display(), source))]
+    ReadDir { path: PathBuf, source: io::Error },
+
+    #[snafu(display("No WAV files found in {}", path.display()))]
+    NoAudio { path: PathBuf },
+
+    #[snafu(display("Invalid column count \"{}\"", value))]
+    InvalidColumns { value: String },
+
+    #[snafu(display("{}", message))]
+    InvalidCentering { message: String },
+
+    #[snafu(display("Failed to find master file {}: {}", path.display(), source))]
+    MasterPath { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to load master audio: {}", source))]
+    MasterLoad {
+        source: crate::audio::source::LoadError,
+    },
+
+    #[snafu(display("{}", source))]
+    WriteProject { source: state::WriteError },
+}
+
+fn is_wav(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("wav"))
+            .unwrap_or(false)
+}
+
+fn looks_like_master(path: &Path) -> bool {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase().contains("master"))
+        .unwrap_or(false)
+}
+
+fn _run(matches: &clap::ArgMatches) -> Result<(), Error> {
+    let dir = Path::new(matches.value_of("DIR").unwrap()); // required arg
+    let output = matches
+        .value_of("OUTPUT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dir.join("project.rprj"));
+
+    let columns = match matches.value_of("COLUMNS") {
+        Some(c) => Some(
+            c.parse::<u32>()
+                .ok()
+                .filter(|c| *c > 0)
+                .context(InvalidColumns { value: c })?,
+        ),
+        None => None,
+    };
+
+    let centering = matches.value_of("CENTERING").unwrap_or("zero-crossing");
+    if let Err(message) = centering.parse::<Centering>() {
+        return Err(Error::InvalidCentering { message });
+    }
+
+    // find audio files
+    let mut files = fs::read_dir(dir)
+        .context(ReadDir { path: dir })?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_wav(path))
+        .map(|path| path.canonicalize().unwrap_or(path))
+        .collect::<Vec<_>>();
+
+    match matches.value_of("SORT") {
+        Some("modified") => {
+            files.sort_by_key(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        }
+        _ => files.sort(),
+    }
+    if matches.is_present("REVERSE") {
+        files.reverse();
+    }
+
+    let master = match matches.value_of("MASTER") {
+        Some(path) => Some(
+            Path::new(path)
+                .canonicalize()
+                .context(MasterPath { path })?,
+        ),
+        None => files.iter().find(|path| looks_like_master(path)).cloned(),
+    };
+
+    // build project
+    let mut state = State::default();
+    for path in files.iter().filter(|path| Some(*path) != master.as_ref()) {
+        match state.add_source_with_scopes(AudioSource::new(path.clone()), master.is_none()) {
+            Ok(names) => {
+                for name in names {
+                    if let Some(scope) = state.scopes.get_mut(&name) {
+                        // validated above
+                        scope.centering = centering.parse().unwrap();
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Skipping file: {}", e),
+        }
+    }
+
+    if state.scopes.is_empty() {
+        return Err(Error::NoAudio {
+            path: dir.to_path_buf(),
+        });
+    }
+
+    if let Some(path) = master {
+        tracing::info!("Using {} as master", path.display());
+
+        let mut source = AudioSource::new(path);
+        source.load().context(MasterLoad)?;
+
+        let channels = source
+            .wav_reader
+            .as_ref()
+            .map(|r| r.spec().channels)
+            .unwrap_or(1);
+        let targets = if channels == 1 {
+            vec![(0, MasterChannel::Left), (0, MasterChannel::Right)]
+        } else {
+            vec![(0, MasterChannel::Left), (1, MasterChannel::Right)]
+        };
+        for (channel, master_channel) in targets {
+            source.connections.push(Connection {
+                channel,
+                target: ConnectionTarget::Master {
+                    channel: master_channel,
+                },
+            });
+        }
+
+        state.audio_sources.push(source);
+    }
+
+    state.arrange_grid(columns);
+
+    tracing::info!(
+        "Writing {} scopes ({}x{}) to {}",
+        state.scopes.len(),
+        state.appearance.grid_columns,
+        state.appearance.grid_rows,
+        output.display()
+    );
+    state.write(&output).context(WriteProject)
+}
+
+pub fn run(matches: &clap::ArgMatches) {
+    if let Err(e) = _run(matches) {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs;
 use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
@@ -31,14 +32,42 @@ impl VideoBackend {
     }
 }
 
+/// Where master audio is played
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputBackend {
+    /// The selected audio output device
+    Device,
+    /// Discards audio at realtime pace, for machines without a sound card
+    Null,
+    /// Writes audio to `output_file` at realtime pace
+    File,
+}
+
 #[derive(Clone, Debug, Derivative, Deserialize, Serialize)]
 #[derivative(Default)]
 #[serde(default)]
 pub struct Audio {
+    #[derivative(Default(value = "OutputBackend::Device"))]
+    pub backend: OutputBackend,
+    #[derivative(Default(value = "\"master.wav\".into()"))]
+    pub output_file: PathBuf,
+
     pub host: Option<String>,
     pub device: Option<String>,
+    /// Output sample rate, the device default if unset
+    pub sample_rate: Option<u32>,
     #[derivative(Default(value = "10.0"))]
     pub buffer_ms: f32,
+    /// Added to the estimated output latency, positive values delay the
+    /// picture
+    pub latency_offset_ms: f32,
+
+    // capture device for live mode
+    pub input_host: Option<String>,
+    pub input_device: Option<String>,
+    /// WAV file to loop instead of capturing from `input_device`
+    pub input_file: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Derivative, Deserialize, Serialize)]
@@ -49,11 +78,78 @@ pub struct Video {
     pub backend: VideoBackend,
 }
 
+#[derive(Clone, Debug, Derivative, Deserialize, Serialize)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Files {
+    pub recent: Vec<PathBuf>,
+    /// 0 disables autosaving
+    #[derivative(Default(value = "60"))]
+    pub autosave_secs: u64,
+}
+
+/// Key combinations like "Ctrl+Shift+S", an empty string unbinds the action
+#[derive(Clone, Debug, Derivative, Deserialize, Serialize)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Keybindings {
+    #[derivative(Default(value = "\"Ctrl+N\".into()"))]
+    pub new: String,
+    #[derivative(Default(value = "\"Ctrl+O\".into()"))]
+    pub open: String,
+    #[derivative(Default(value = "\"Ctrl+S\".into()"))]
+    pub save: String,
+    #[derivative(Default(value = "\"Ctrl+Shift+S\".into()"))]
+    pub save_as: String,
+    #[derivative(Default(value = "\"Ctrl+Z\".into()"))]
+    pub undo: String,
+    #[derivative(Default(value = "\"Ctrl+Shift+Z\".into()"))]
+    pub redo: String,
+    #[derivative(Default(value = "\"Space\".into()"))]
+    pub play_pause: String,
+    /// Seeking moves by 100 frames
+    #[derivative(Default(value = "\"Shift+Right\".into()"))]
+    pub seek_forward: String,
+    #[derivative(Default(value = "\"Shift+Left\".into()"))]
+    pub seek_backward: String,
+    #[derivative(Default(value = "\"Home\".into()"))]
+    pub seek_start: String,
+    #[derivative(Default(value = "\"Right\".into()"))]
+    pub step_forward: String,
+    #[derivative(Default(value = "\"Left\".into()"))]
+    pub step_backward: String,
+    #[derivative(Default(value = "\"I\".into()"))]
+    pub loop_in: String,
+    #[derivative(Default(value = "\"O\".into()"))]
+    pub loop_out: String,
+    #[derivative(Default(value = "\"L\".into()"))]
+    pub toggle_loop: String,
+    #[derivative(Default(value = "\"F1\".into()"))]
+    pub toggle_main: String,
+    #[derivative(Default(value = "\"F2\".into()"))]
+    pub toggle_scopes: String,
+    #[derivative(Default(value = "\"F3\".into()"))]
+    pub toggle_sources: String,
+    #[derivative(Default(value = "\"F4\".into()"))]
+    pub toggle_timeline: String,
+    #[derivative(Default(value = "\"F5\".into()"))]
+    pub toggle_messages: String,
+    #[derivative(Default(value = "\"F6\".into()"))]
+    pub toggle_log: String,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
     pub audio: Audio,
     pub video: Video,
+    pub files: Files,
+    pub keybindings: Keybindings,
+
+    /// Set if the config file exists but could not be loaded, so that the
+    /// defaults used instead don't replace it
+    #[serde(skip)]
+    load_failed: bool,
 }
 
 #[derive(Debug, Snafu)]
@@ -72,10 +168,13 @@ pub enum Error {
 
     #[snafu(display("Failed to write to config file: {}", source))]
     WriteError { source: io::Error },
+
+    #[snafu(display("Not overwriting {}, it could not be loaded", path.display()))]
+    LoadFailed { path: PathBuf },
 }
 
 impl Config {
-    fn config_dir() -> Result<directories_next::ProjectDirs, Error> {
+    pub fn config_dir() -> Result<directories_next::ProjectDirs, Error> {
         directories_next::ProjectDirs::from("", "rytone", "rawrscope").context(HomeDirectory)
     }
 
@@ -83,6 +182,11 @@ impl Config {
         let sp = tracing::debug_span!("load_config");
         let _e = sp.enter();
 
+        let failed = || Config {
+            load_failed: true,
+            ..Default::default()
+        };
+
         let mut path = match Config::config_dir() {
             Ok(p) => p,
             Err(_) => {
@@ -102,25 +206,39 @@ impl Config {
             }
             Err(e) => {
                 tracing::warn!(err = %e, "Failed to load config... using default");
-                return Default::default();
+                return failed();
             }
         };
 
         let mut buffer = Vec::new();
         if let Err(e) = file.read_to_end(&mut buffer) {
             tracing::warn!(err = %e, "Failed to read config... using default");
-            return Default::default();
+            return failed();
         }
 
         match toml::from_slice(&buffer) {
             Ok(config) => config,
             Err(e) => {
                 tracing::warn!(err = %e, "Failed to parse config... using default");
-                Default::default()
+                failed()
             }
         }
     }
 
+    /// Moves a project to the top of the recent projects list and saves the
+    /// config
+    pub fn add_recent_project(&mut self, path: &Path) {
+        const MAX_RECENT: usize = 10;
+
+        self.files.recent.retain(|p| p != path);
+        self.files.recent.insert(0, path.to_path_buf());
+        self.files.recent.truncate(MAX_RECENT);
+
+        if let Err(e) = self.write() {
+            tracing::warn!("Failed to save recent projects: {}", e);
+        }
+    }
+
     pub fn write(&self) -> Result<(), Error> {
         let dir = Config::config_dir()?;
 
@@ -132,6 +250,10 @@ impl Config {
         let mut path = dir.config_dir().to_path_buf();
         path.push("rawrscope.toml");
 
+        if self.load_failed {
+            return Err(Error::LoadFailed { path });
+        }
+
         let mut file = fs::File::create(path).context(CreateError)?;
         let serialized = toml::to_string_pretty(self).context(SerializeError)?;
         file.write_all(serialized.as_ref()).context(WriteError)
@@ -0,0 +1,179 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::{self, Write};
+
+use parking_lot::{const_mutex, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{span, subscriber::Interest, Level, Metadata};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, EnvFilter, Layer};
+
+const CAPACITY: usize = 5000;
+
+// the log window shows our debug output even if the console filter hides it
+fn captured(metadata: &Metadata<'_>) -> bool {
+    let level = if metadata.target().starts_with("rawrscope") {
+        Level::DEBUG
+    } else {
+        Level::WARN
+    };
+    *metadata.level() <= level
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    pub level: tracing::Level,
+    pub target: &'static str,
+    /// names of the entered spans, outermost first
+    pub spans: Vec<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>5} ", self.level)?;
+        for span in &self.spans {
+            write!(f, "{}:", span)?;
+        }
+        if !self.spans.is_empty() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Default)]
+pub struct Buffer {
+    pub entries: VecDeque<Entry>,
+    /// every span name that has been seen so far
+    pub span_names: BTreeSet<&'static str>,
+}
+
+static BUFFER: Mutex<Option<Buffer>> = const_mutex(None);
+
+/// Runs `f` with the captured log, holding its lock
+pub fn with_buffer<R, F: FnOnce(&mut Buffer) -> R>(f: F) -> R {
+    f(BUFFER.lock().get_or_insert_with(Default::default))
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Captures events into a ring buffer for the log window
+struct CaptureLayer;
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        let spans = ctx.scope().map(|span| span.name()).collect::<Vec<_>>();
+        let metadata = event.metadata();
+
+        with_buffer(|buffer| {
+            buffer.span_names.extend(spans.iter().copied());
+            buffer.entries.push_back(Entry {
+                level: *metadata.level(),
+                target: metadata.target(),
+                spans,
+                message: visitor.message + &visitor.fields,
+            });
+            if buffer.entries.len() > CAPACITY {
+                buffer.entries.pop_front();
+            }
+        });
+    }
+}
+
+/// Captures events for the log window and passes the ones allowed by `filter`
+/// on to `output`. A global filter would also limit what is captured.
+pub struct Layers<L> {
+    output: L,
+    filter: EnvFilter,
+}
+
+impl<L> Layers<L> {
+    pub fn new(output: L, filter: EnvFilter) -> Self {
+        Layers { output, filter }
+    }
+}
+
+impl<S, L> Layer<S> for Layers<L>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    L: Layer<S>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        let interest = Layer::<S>::register_callsite(&self.filter, metadata);
+        if captured(metadata) {
+            Interest::always()
+        } else {
+            interest
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        captured(metadata) || self.filter.enabled(metadata, ctx)
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.new_span(attrs, id, ctx.clone());
+        self.output.new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        self.filter.on_record(id, values, ctx.clone());
+        self.output.on_record(id, values, ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        self.output.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        CaptureLayer.on_event(event, ctx.clone());
+        if self.filter.enabled(event.metadata(), ctx.clone()) {
+            self.output.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_enter(id, ctx.clone());
+        self.output.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_exit(id, ctx.clone());
+        self.output.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let output = ctx
+            .metadata(&id)
+            .map(|metadata| self.filter.enabled(metadata, ctx.clone()))
+            .unwrap_or(false);
+        if output {
+            self.output.on_close(id.clone(), ctx.clone());
+        }
+        self.filter.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        self.filter.on_id_change(old, new, ctx.clone());
+        self.output.on_id_change(old, new, ctx);
+    }
+}
@@ -4,26 +4,36 @@ mod args;
 mod audio;
 mod commands;
 mod config;
+mod logging;
 mod panic;
+mod recovery;
 mod render;
 mod scope;
 mod state;
+#[cfg(test)]
+mod test_util;
 mod ui;
 
+use tracing_subscriber::layer::SubscriberExt;
+
 fn main() {
     let matches = args::get().get_matches();
 
     tracing_log::LogTracer::init().expect("Failed to initialize log -> tracing compat");
-    let log_sub = tracing_subscriber::fmt()
-        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .finish();
+    let log_sub = tracing_subscriber::registry().with(logging::Layers::new(
+        tracing_subscriber::fmt::layer()
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE),
+        tracing_subscriber::EnvFilter::from_default_env(),
+    ));
     tracing::subscriber::set_global_default(log_sub)
         .expect("Failed to set global tracing subscriber");
 
     match matches.subcommand_name() {
-        None => commands::app::run(matches.value_of("PROJECT")),
-        Some("configure_audio") => commands::configure_audio::run(),
+        None => commands::app::run(matches.value_of("PROJECT"), matches.is_present("LIVE")),
+        Some("configure_audio") => {
+            commands::configure_audio::run(matches.subcommand_matches("configure_audio").unwrap())
+        }
+        Some("new") => commands::new::run(matches.subcommand_matches("new").unwrap()),
         _ => unimplemented!(),
     }
 }
@@ -19,11 +19,17 @@ pub fn dialog(old_handler: PanicHandler) -> PanicHandler {
             },
         };
 
+        let recovery_note = if crate::recovery::emergency_save() {
+            "\nUnsaved changes will be offered for recovery on the next launch."
+        } else {
+            ""
+        };
+
         tfd::message_box_ok(
             "Oops!",
             &format!(
-                "rawrscope encontered an unrecoverable error!\n{}\n(at {})",
-                info, location
+                "rawrscope encontered an unrecoverable error!\n{}\n(at {}){}",
+                info, location, recovery_note
             ),
             tfd::MessageBoxIcon::Error,
         );
@@ -0,0 +1,296 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use parking_lot::{const_mutex, Mutex};
+use tinyfiledialogs as tfd;
+
+use crate::config;
+use crate::state::State;
+
+// Every running instance has its own lock and recovery file, named after its
+// process id. The lock contains the path of the open project, a lock whose
+// process is gone belongs to a session that was killed. Where that can't be
+// told, the lock is touched regularly and one that wasn't touched for a while
+// counts as dead. Sessions that panicked leave a crash file instead of the
+// lock.
+const LOCK_EXT: &str = "lock";
+const CRASH_EXT: &str = "crashed";
+
+/// How often a running session touches its lock
+pub const HEARTBEAT: Duration = Duration::from_secs(10);
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+// latest unsaved project and its path, for saving from the panic hook
+static EMERGENCY_SNAPSHOT: Mutex<Option<(PathBuf, String)>> = const_mutex(None);
+
+fn recovery_dir() -> Option<PathBuf> {
+    let dirs = config::Config::config_dir().ok()?;
+    let dir = dirs.config_dir();
+    if let Err(e) = fs::DirBuilder::new().recursive(true).create(dir) {
+        tracing::warn!("Failed to create config directory: {}", e);
+        return None;
+    }
+    Some(dir.to_path_buf())
+}
+
+fn session_file(dir: &Path, pid: u32, ext: &str) -> PathBuf {
+    dir.join(format!("session-{}.{}", pid, ext))
+}
+
+// pid of a file named by `session_file`
+fn session_pid(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    if !stem.starts_with("session-") {
+        return None;
+    }
+    stem["session-".len()..].parse().ok()
+}
+
+fn recovery_file(dir: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("recovery-{}.rprj", pid))
+}
+
+fn write_lock(dir: &Path, project: &Path) {
+    let path = session_file(dir, std::process::id(), LOCK_EXT);
+    if let Err(e) = fs::write(path, project.to_string_lossy().as_bytes()) {
+        tracing::warn!("Failed to write session lock: {}", e);
+    }
+}
+
+// whether a process with the pid is running, None if that can't be told
+#[cfg(unix)]
+fn process_alive(pid: u32) -> Option<bool> {
+    // signal 0 only checks whether the process exists
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return Some(true);
+    }
+    match std::io::Error::last_os_error().raw_os_error() {
+        Some(libc::ESRCH) => Some(false),
+        // running as another user
+        Some(libc::EPERM) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> Option<bool> {
+    use winapi::shared::winerror::{ERROR_ACCESS_DENIED, ERROR_INVALID_PARAMETER};
+    use winapi::um::{
+        errhandlingapi::GetLastError, handleapi::CloseHandle, minwinbase::STILL_ACTIVE,
+        processthreadsapi::GetExitCodeProcess, processthreadsapi::OpenProcess,
+        winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return match GetLastError() {
+                ERROR_INVALID_PARAMETER => Some(false),
+                ERROR_ACCESS_DENIED => Some(true),
+                _ => None,
+            };
+        }
+
+        let mut exit_code = 0;
+        let queried = GetExitCodeProcess(handle, &mut exit_code) != 0;
+        CloseHandle(handle);
+        if queried {
+            Some(exit_code == STILL_ACTIVE)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+// another instance that crashed or was killed, with its lock or crash file
+struct DeadSession {
+    pid: u32,
+    file: PathBuf,
+    modified: SystemTime,
+}
+
+// most recent first
+fn dead_sessions(dir: &Path) -> Vec<DeadSession> {
+    let own_pid = std::process::id();
+    let now = SystemTime::now();
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Failed to look for previous sessions: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut sessions = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let ext = path.extension()?.to_str()?;
+            let pid = session_pid(&path)?;
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+
+            let dead = match ext {
+                CRASH_EXT => true,
+                // the heartbeat stops while blocked in a dialog
+                LOCK_EXT => match process_alive(pid) {
+                    Some(alive) => !alive,
+                    None => now
+                        .duration_since(modified)
+                        .map(|age| age > STALE_AFTER)
+                        .unwrap_or(false),
+                },
+                _ => false,
+            };
+            if dead && pid != own_pid {
+                Some(DeadSession {
+                    pid,
+                    file: path,
+                    modified,
+                })
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    sessions.sort_by(|a, b| b.modified.cmp(&a.modified));
+    sessions
+}
+
+/// Offers to restore the autosave of the last session that did not exit
+/// cleanly. Sessions of other running instances are left alone.
+pub fn restore_previous_session() -> Option<State> {
+    let sp = tracing::debug_span!("restore_session");
+    let _e = sp.enter();
+
+    let dir = recovery_dir()?;
+    let mut sessions = dead_sessions(&dir);
+
+    // sessions without unsaved changes have nothing to restore
+    sessions.retain(|session| {
+        let has_recovery = recovery_file(&dir, session.pid).exists();
+        if !has_recovery {
+            let _ = fs::remove_file(&session.file);
+        }
+        has_recovery
+    });
+    let session = sessions.first()?;
+    let recovery_path = recovery_file(&dir, session.pid);
+
+    let restore = tfd::message_box_yes_no(
+        "Restore Project?",
+        "rawrscope did not exit cleanly last time. Restore unsaved changes?",
+        tfd::MessageBoxIcon::Question,
+        tfd::YesNo::Yes,
+    ) == tfd::YesNo::Yes;
+
+    let project_path = fs::read_to_string(&session.file).unwrap_or_default();
+    let _ = fs::remove_file(&session.file);
+    if !restore {
+        let _ = fs::remove_file(&recovery_path);
+        return None;
+    }
+
+    match State::from_file(&recovery_path) {
+        Ok((mut state, warnings)) => {
+            for w in warnings {
+                state.ui.messages.warn(w.to_string());
+            }
+            state.file_path = PathBuf::from(project_path);
+            state.dirty = true;
+
+            // ours now, until the next autosave replaces it
+            let own_recovery = recovery_file(&dir, std::process::id());
+            if let Err(e) = fs::rename(&recovery_path, own_recovery) {
+                tracing::warn!("Failed to take over recovery file: {}", e);
+            }
+            Some(state)
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to restore project from {}: {}",
+                recovery_path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Marks the session as running
+pub fn begin_session(state: &State) {
+    if let Some(dir) = recovery_dir() {
+        write_lock(&dir, &state.file_path);
+    }
+}
+
+/// Shows that the session is still running, should be called every
+/// `HEARTBEAT`
+pub fn keep_alive(state: &State) {
+    if let Some(dir) = recovery_dir() {
+        write_lock(&dir, &state.file_path);
+    }
+}
+
+/// Marks the session as cleanly exited
+pub fn end_session() {
+    if let Some(dir) = recovery_dir() {
+        let pid = std::process::id();
+        let _ = fs::remove_file(recovery_file(&dir, pid));
+        let _ = fs::remove_file(session_file(&dir, pid, LOCK_EXT));
+    }
+}
+
+pub fn autosave(state: &mut State) {
+    let sp = tracing::debug_span!("autosave");
+    let _e = sp.enter();
+
+    if let Some(dir) = recovery_dir() {
+        match state.write_with_paths(recovery_file(&dir, std::process::id()), true) {
+            Ok(()) => write_lock(&dir, &state.file_path),
+            Err(e) => tracing::warn!("Autosave failed: {}", e),
+        }
+    }
+}
+
+/// Keeps the snapshot used by `emergency_save` up to date
+pub fn update_emergency_snapshot(state: &State) {
+    let snapshot = match state.history.current() {
+        Some(s) if state.dirty => Some((state.file_path.clone(), s.to_string())),
+        _ => None,
+    };
+    *EMERGENCY_SNAPSHOT.lock() = snapshot;
+}
+
+/// Writes the last snapshot to the recovery file, returns whether anything
+/// was saved. Only meant to be called when panicking.
+pub fn emergency_save() -> bool {
+    // the panicking thread could be holding the lock
+    let guard = match EMERGENCY_SNAPSHOT.try_lock() {
+        Some(g) => g,
+        None => return false,
+    };
+    let (project_path, snapshot) = match guard.as_ref() {
+        Some(s) => s,
+        None => return false,
+    };
+
+    match recovery_dir() {
+        Some(dir) => {
+            let pid = std::process::id();
+            let saved = fs::write(recovery_file(&dir, pid), snapshot).is_ok();
+
+            // lets the next start offer the recovery right away
+            let crash_file = session_file(&dir, pid, CRASH_EXT);
+            let _ = fs::write(crash_file, project_path.to_string_lossy().as_bytes());
+            let _ = fs::remove_file(session_file(&dir, pid, LOCK_EXT));
+            saved
+        }
+        None => false,
+    }
+}
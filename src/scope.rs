@@ -6,6 +6,8 @@ use crate::state::GridRect;
 pub mod centering;
 use centering::Algorithm;
 
+pub mod filter;
+
 // custom impl of std::option::IntoIter in order to expose inner value
 struct SubmissionSlot {
     inner: Option<mixer::Submission>,
@@ -50,6 +52,9 @@ pub struct Scope {
     pub trigger_width: f32,
     pub centering: centering::Centering,
 
+    #[serde(default)]
+    pub filters: Vec<filter::Stage>,
+
     #[serde(skip)]
     mixer: Option<mixer::Mixer<SubmissionSlot>>,
 
@@ -61,6 +66,20 @@ pub struct Scope {
 }
 
 impl Scope {
+    pub fn new(rect: GridRect) -> Self {
+        Scope {
+            window_size: 0.05,
+            line_width: 2.0,
+            rect,
+            trigger_width: 0.05,
+            centering: centering::Centering::ZeroCrossing(centering::ZeroCrossing),
+            filters: Vec::new(),
+            mixer: None,
+            audio: Vec::new(),
+            center_offset: 0,
+        }
+    }
+
     pub fn wanted_length(&self) -> f32 {
         self.window_size + self.trigger_width
     }
@@ -103,6 +122,9 @@ impl Scope {
         let output_size = (sample_rate as f32 * self.window_size) as usize;
 
         self.audio = mixer.next().expect("attempted to process no audio!");
+        for stage in &self.filters {
+            stage.apply(&mut self.audio, sample_rate);
+        }
 
         let trigger_samples = (sample_rate as f32 * self.trigger_width) as usize;
         let trigger_pad = (self.audio.len() - trigger_samples) / 2;
@@ -39,3 +39,16 @@ impl std::fmt::Display for Centering {
         }
     }
 }
+
+impl std::str::FromStr for Centering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Centering::NoCentering(NoCentering)),
+            "zero-crossing" => Ok(Centering::ZeroCrossing(ZeroCrossing)),
+            "fundamental-phase" => Ok(Centering::FundamentalPhase(FundamentalPhase::default())),
+            _ => Err(format!("Unknown centering algorithm \"{}\"", s)),
+        }
+    }
+}
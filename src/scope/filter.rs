@@ -0,0 +1,90 @@
+use imgui::im_str;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::filter::{highpass, lowpass};
+
+// display-only processing, applied after the scope mixer and before centering
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Stage {
+    LowPass { cutoff: f32 },
+    HighPass { cutoff: f32 },
+    BandPass { low: f32, high: f32 },
+    Gain { db: f32 },
+    Rectify { full: bool },
+}
+
+impl Stage {
+    pub fn defaults() -> [Stage; 5] {
+        [
+            Stage::LowPass { cutoff: 5000.0 },
+            Stage::HighPass { cutoff: 20.0 },
+            Stage::BandPass {
+                low: 20.0,
+                high: 5000.0,
+            },
+            Stage::Gain { db: 0.0 },
+            Stage::Rectify { full: true },
+        ]
+    }
+
+    pub fn apply(&self, samples: &mut [f32], sample_rate: u32) {
+        match *self {
+            Stage::LowPass { cutoff } => lowpass(samples, sample_rate, cutoff),
+            Stage::HighPass { cutoff } => highpass(samples, sample_rate, cutoff),
+            Stage::BandPass { low, high } => {
+                highpass(samples, sample_rate, low);
+                lowpass(samples, sample_rate, high);
+            }
+            Stage::Gain { db } => {
+                let gain = 10f32.powf(db / 20.0);
+                for v in samples.iter_mut() {
+                    *v *= gain;
+                }
+            }
+            Stage::Rectify { full } => {
+                for v in samples.iter_mut() {
+                    *v = if full { v.abs() } else { v.max(0.0) };
+                }
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &imgui::Ui) -> bool {
+        let hz_drag = |label: &imgui::ImStr, value: &mut f32| {
+            imgui::DragFloat::new(ui, label, value)
+                .min(1.0)
+                .max(20000.0)
+                .speed(1.0)
+                .display_format(im_str!("%.1f Hz"))
+                .build()
+        };
+
+        match self {
+            Stage::LowPass { cutoff } | Stage::HighPass { cutoff } => {
+                hz_drag(im_str!("Cutoff"), cutoff)
+            }
+            Stage::BandPass { low, high } => {
+                hz_drag(im_str!("Low Cutoff"), low) | hz_drag(im_str!("High Cutoff"), high)
+            }
+            Stage::Gain { db } => imgui::DragFloat::new(ui, im_str!("Gain"), db)
+                .min(-48.0)
+                .max(48.0)
+                .speed(0.1)
+                .display_format(im_str!("%.1f dB"))
+                .build(),
+            Stage::Rectify { full } => ui.checkbox(im_str!("Full Wave"), full),
+        }
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::LowPass { .. } => write!(f, "Low-pass"),
+            Stage::HighPass { .. } => write!(f, "High-pass"),
+            Stage::BandPass { .. } => write!(f, "Band-pass"),
+            Stage::Gain { .. } => write!(f, "Gain"),
+            Stage::Rectify { .. } => write!(f, "Rectify"),
+        }
+    }
+}
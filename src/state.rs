@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 use std::fs;
-use std::io;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 
 use derivative::Derivative;
@@ -11,6 +11,11 @@ use snafu::{ResultExt, Snafu};
 use crate::audio;
 use crate::scope;
 
+mod framerate;
+mod migrate;
+pub use framerate::Framerate;
+pub use migrate::CURRENT_VERSION;
+
 #[derive(Debug, Snafu)]
 pub enum ReadError {
     #[snafu(display("Failed to open project from {}: {}", path.display(), source))]
@@ -19,11 +24,31 @@ pub enum ReadError {
     #[snafu(display("Failed to canonicalize project path: {}", source))]
     PathError { source: io::Error },
 
-    #[snafu(display("Failed to move into project directory: {}", source))]
-    EnvError { source: io::Error },
+    #[snafu(display("Failed to read project: {}", source))]
+    ReadFileError { source: io::Error },
 
     #[snafu(display("Failed to parse project: {}", source))]
     ParseError { source: serde_yaml::Error },
+
+    #[snafu(display(
+        "Project uses format version {}, but this version of rawrscope only supports up to version {}",
+        version,
+        supported
+    ))]
+    UnsupportedVersion { version: u64, supported: u32 },
+
+    #[snafu(display(
+        "Failed to upgrade project from format version {}: {}",
+        version,
+        message
+    ))]
+    MigrationError { version: u32, message: String },
+
+    #[snafu(display("Invalid project (saved with format version {}): {}", version, source))]
+    SchemaError {
+        version: u32,
+        source: serde_yaml::Error,
+    },
 }
 
 #[derive(Debug, Snafu)]
@@ -31,6 +56,9 @@ pub enum WriteError {
     #[snafu(display("Failed to open project from {} for writing: {}", path.display(), source))]
     CreateError { path: PathBuf, source: io::Error },
 
+    #[snafu(display("Failed to resolve project directory: {}", source))]
+    DirError { source: io::Error },
+
     #[snafu(display("Failed to write project: {}", source))]
     IoError { source: io::Error },
 
@@ -41,8 +69,7 @@ pub enum WriteError {
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(Default)]
 pub struct GlobalAppearance {
-    #[derivative(Default(value = "60"))]
-    pub framerate: u32,
+    pub framerate: Framerate,
     #[derivative(Default(value = "1"))]
     pub grid_rows: u32,
     #[derivative(Default(value = "1"))]
@@ -50,7 +77,7 @@ pub struct GlobalAppearance {
 }
 
 // TODO maybe move some of this stuff into a separate module
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Copy, Deserialize, Serialize)]
 pub struct GridRect {
     pub x: u32,
     pub y: u32,
@@ -64,6 +91,93 @@ pub struct PlaybackState {
     pub frame: u32,
     #[derivative(Default(value = "false"))]
     pub playing: bool,
+    /// Master audio is played while paused because the playhead is dragged
+    pub scrubbing: bool,
+    /// First frame of the loop region
+    pub loop_in: Option<u32>,
+    /// Frame where the loop region ends (exclusive), loops at the end of the
+    /// audio if not set
+    pub loop_out: Option<u32>,
+    pub looping: bool,
+    /// Playback speed relative to realtime
+    #[derivative(Default(value = "1.0"))]
+    pub speed: f32,
+    /// Position between `frame` and the next frame, used when playing slower
+    /// than realtime
+    pub subframe: f32,
+    /// Project position (frames) of the next master audio submission, runs
+    /// ahead of `frame` by the output latency while playing
+    pub write_position: f64,
+    /// Set when seeking, until the markers of audio queued from the old
+    /// position have been forgotten. The audio itself still plays out.
+    pub seeked: bool,
+}
+
+impl PlaybackState {
+    pub const SPEEDS: [f32; 3] = [1.0, 0.5, 0.25];
+
+    /// Displayed position in frames, including the subframe
+    pub fn position(&self) -> f64 {
+        f64::from(self.frame) + f64::from(self.subframe)
+    }
+
+    /// Moves the displayed position without seeking
+    pub fn set_position(&mut self, position: f64) {
+        let position = position.max(0.0);
+        self.frame = position.floor() as u32;
+        self.subframe = position.fract() as f32;
+    }
+
+    /// Advances the write position by one tick of the project framerate
+    pub fn advance(&mut self) {
+        self.write_position += f64::from(self.speed);
+    }
+
+    pub fn seek(&mut self, frame: u32) {
+        self.frame = frame;
+        self.subframe = 0.0;
+        self.write_position = f64::from(frame);
+        self.seeked = true;
+    }
+
+    /// Moves by whole frames and pauses, for stepping through frame by frame
+    pub fn step(&mut self, frames: i32) {
+        self.playing = false;
+        if frames < 0 {
+            self.seek(self.frame.saturating_sub(frames.unsigned_abs()));
+        } else {
+            self.seek(self.frame.saturating_add(frames as u32));
+        }
+    }
+
+    /// Displayed position in frames of audio at `sample_rate`
+    pub fn playhead(&self, sample_rate: u32, framerate: Framerate) -> u32 {
+        framerate.position_to_samples(self.position(), sample_rate) as u32
+    }
+
+    /// Write position in frames of audio at `sample_rate`
+    pub fn write_playhead(&self, sample_rate: u32, framerate: Framerate) -> u32 {
+        framerate.position_to_samples(self.write_position, sample_rate) as u32
+    }
+
+    /// Frame to move the write position back to if it has run past the loop
+    /// region
+    pub fn loop_restart(&self, exhausted: bool) -> Option<u32> {
+        if !self.looping {
+            return None;
+        }
+
+        let start = self.loop_in.unwrap_or(0);
+        let past_end = match self.loop_out {
+            Some(end) if end > start => self.write_position >= f64::from(end),
+            _ => exhausted,
+        };
+        if past_end {
+            Some(start)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Derivative)]
@@ -83,20 +197,88 @@ pub struct UiState {
     #[derivative(Default(value = "true"))]
     pub show_scopes: bool,
     #[derivative(Default(value = "false"))]
+    pub show_sources: bool,
+    #[derivative(Default(value = "true"))]
+    pub show_timeline: bool,
+    #[derivative(Default(value = "false"))]
     pub show_debug: bool,
+    #[derivative(Default(value = "false"))]
+    pub show_messages: bool,
+    pub messages: crate::ui::messages::Messages,
+    #[derivative(Default(value = "false"))]
+    pub show_log: bool,
+    pub log: crate::ui::log::LogConsole,
+    pub timeline: crate::ui::timeline::Timeline,
+    #[derivative(Default(value = "false"))]
+    pub show_audio_settings: bool,
+    pub audio_settings: crate::ui::audio_settings::AudioSettings,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<String>,
+    redo: Vec<String>,
+    current: Option<String>,
+    pending: bool,
+    generation: u64,
+}
+
+impl History {
+    /// Serialized project as of the last recorded change
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Changes whenever the current snapshot does
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
 }
 
 #[derive(Default, Deserialize, Serialize)]
+pub struct LiveInput {
+    pub connections: Vec<audio::connection::Connection>,
+
+    /// Rate of the open capture device, if any
+    #[serde(skip)]
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(Derivative, Deserialize, Serialize)]
+#[derivative(Default)]
 pub struct State {
+    /// Missing before version 1, always `CURRENT_VERSION` once loaded
+    #[derivative(Default(value = "CURRENT_VERSION"))]
+    #[serde(default)]
+    pub version: u32,
     pub audio_sources: Vec<audio::source::AudioSource>,
     pub scopes: LinkedHashMap<String, scope::Scope>,
     pub appearance: GlobalAppearance,
+    /// Store absolute source paths instead of paths relative to the project
+    #[serde(default)]
+    pub absolute_paths: bool,
+    /// Routing of the capture device in live mode
+    #[serde(default)]
+    pub live_input: Option<LiveInput>,
 
     #[serde(skip)]
     pub file_path: PathBuf,
+    /// Set when the project has unsaved changes
+    #[serde(skip)]
+    pub dirty: bool,
 
     #[serde(skip)]
     pub playback: PlaybackState,
+    #[serde(skip)]
+    pub history: History,
 
     #[serde(skip)]
     pub ui: UiState,
@@ -104,6 +286,30 @@ pub struct State {
     pub debug: DebugState,
 }
 
+// both paths should be absolute, returns None if they share no common root
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path_components = path.components().collect::<Vec<_>>();
+    let base_components = base.components().collect::<Vec<_>>();
+
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component.as_os_str());
+    }
+    Some(relative)
+}
+
 impl State {
     pub fn from_file<P: AsRef<Path>>(
         path: P,
@@ -113,14 +319,38 @@ impl State {
 
         let canonical_path = path.canonicalize().context(PathError)?;
 
-        let file = fs::File::open(path).context(OpenError {
+        let mut file = fs::File::open(path).context(OpenError {
             path: path.to_path_buf(),
         })?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).context(ReadFileError)?;
 
-        // TODO apply this whenever path changes (part of new state management)
-        std::env::set_current_dir(canonical_path.parent().unwrap()).context(EnvError)?;
+        let mut doc: serde_yaml::Value = serde_yaml::from_str(&text).context(ParseError)?;
+        let migrated = migrate::migrate(&mut doc)?;
+        let version = migrated.version;
+        if version != CURRENT_VERSION {
+            tracing::info!(
+                from = version,
+                to = CURRENT_VERSION,
+                "Upgraded project format"
+            );
+        }
 
-        let mut state: State = serde_yaml::from_reader(file).context(ParseError)?;
+        // deserialize from the file's text if possible, so that errors point
+        // at the offending line. Rewritten documents have no lines to point at.
+        let mut state: State = if migrated.changed {
+            serde_yaml::from_value(doc)
+        } else {
+            serde_yaml::from_str(&text)
+        }
+        .context(SchemaError { version })?;
+        state.version = CURRENT_VERSION;
+        // source paths are relative to the project file
+        let project_dir = canonical_path.parent().unwrap();
+        for source in state.audio_sources.iter_mut() {
+            let path = project_dir.join(&source.path);
+            source.path = path.canonicalize().unwrap_or(path);
+        }
         state.file_path = canonical_path;
 
         // load audio sources
@@ -132,40 +362,520 @@ impl State {
                 .map(|b| b as Box<dyn std::error::Error>),
         );
 
-        // initialize scope mixers
-        for (scope_name, scope) in state.scopes.iter_mut() {
-            let sample_rates = state
+        state.configure_mixers();
+
+        Ok((state, warnings))
+    }
+
+    pub fn configure_mixers(&mut self) {
+        for (scope_name, scope) in self.scopes.iter_mut() {
+            let mut sample_rates = self
                 .audio_sources
                 .iter_mut()
                 .filter(|source| {
-                    source.connections.iter().any(|conn| match &conn.target {
-                        audio::connection::ConnectionTarget::Scope { name, .. } => {
-                            name == scope_name
-                        }
-                        _ => false,
-                    })
+                    source
+                        .connections
+                        .iter()
+                        .any(|conn| conn.target.targets_scope(scope_name))
                 })
                 .filter_map(|source| source.as_loaded())
                 .map(|loaded| loaded.spec().sample_rate)
                 .collect::<Vec<_>>();
 
+            if let Some(live) = &self.live_input {
+                if let Some(sample_rate) = live.sample_rate {
+                    if live
+                        .connections
+                        .iter()
+                        .any(|conn| conn.target.targets_scope(scope_name))
+                    {
+                        sample_rates.push(sample_rate);
+                    }
+                }
+            }
+
             scope.configure_mixer(sample_rates);
         }
+    }
 
-        Ok((state, warnings))
+    fn unique_scope_name(&self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 2;
+        while self.scopes.contains_key(&name) {
+            name = format!("{} ({})", base, n);
+            n += 1;
+        }
+        name
+    }
+
+    /// Adds a source along with one scope per channel, optionally routing it
+    /// to master. Call `place_scopes` or `arrange_grid` and `configure_mixers`
+    /// afterwards.
+    pub fn add_source_with_scopes(
+        &mut self,
+        mut source: audio::source::AudioSource,
+        to_master: bool,
+    ) -> Result<Vec<String>, audio::source::LoadError> {
+        use audio::connection::{Connection, ConnectionTarget, MasterChannel};
+
+        if !source.is_loaded() {
+            source.load()?;
+        }
+
+        let channels = source
+            .wav_reader
+            .as_ref()
+            .map(|r| r.spec().channels)
+            .unwrap_or(1);
+        let stem = source
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Scope".to_string());
+
+        let mut names = Vec::new();
+        for channel in 0..u32::from(channels) {
+            let name = if channels > 1 {
+                self.unique_scope_name(&format!("{} {}", stem, channel + 1))
+            } else {
+                self.unique_scope_name(&stem)
+            };
+
+            self.scopes.insert(
+                name.clone(),
+                scope::Scope::new(GridRect {
+                    x: 0,
+                    y: 0,
+                    w: 1,
+                    h: 1,
+                }),
+            );
+            source.connections.push(Connection {
+                channel,
+                target: ConnectionTarget::Scope {
+                    name: name.clone(),
+                    channel: 0,
+                    filter: None,
+                },
+            });
+            names.push(name);
+
+            if to_master {
+                // mono sources go to both sides
+                let master_channels = if channels == 1 {
+                    vec![MasterChannel::Left, MasterChannel::Right]
+                } else if channel % 2 == 0 {
+                    vec![MasterChannel::Left]
+                } else {
+                    vec![MasterChannel::Right]
+                };
+                for master_channel in master_channels {
+                    source.connections.push(Connection {
+                        channel,
+                        target: ConnectionTarget::Master {
+                            channel: master_channel,
+                        },
+                    });
+                }
+            }
+        }
+
+        self.audio_sources.push(source);
+        Ok(names)
+    }
+
+    /// Routes a capture device with the given format to the scopes, adding
+    /// one scope per channel in free grid cells if the project has no live
+    /// routing yet. Calls `configure_mixers`.
+    pub fn start_live_input(&mut self, channels: u16, sample_rate: u32) {
+        use audio::connection::{Connection, ConnectionTarget};
+
+        let needs_scopes = self
+            .live_input
+            .as_ref()
+            .map(|l| l.connections.is_empty())
+            .unwrap_or(true);
+        if needs_scopes {
+            let mut names = Vec::new();
+            let mut connections = Vec::new();
+            for channel in 0..u32::from(channels) {
+                let name = self.unique_scope_name(&format!("Input {}", channel + 1));
+                self.scopes.insert(
+                    name.clone(),
+                    scope::Scope::new(GridRect {
+                        x: 0,
+                        y: 0,
+                        w: 1,
+                        h: 1,
+                    }),
+                );
+                connections.push(Connection {
+                    channel,
+                    target: ConnectionTarget::Scope {
+                        name: name.clone(),
+                        channel: 0,
+                        filter: None,
+                    },
+                });
+                names.push(name);
+            }
+            self.live_input = Some(LiveInput {
+                connections,
+                sample_rate: None,
+            });
+            self.place_scopes(&names);
+            self.mark_changed();
+        }
+
+        if let Some(live) = &mut self.live_input {
+            live.sample_rate = Some(sample_rate);
+        }
+        self.configure_mixers();
+    }
+
+    /// Lays out all scopes in a grid, in order. If `columns` is `None`, the
+    /// grid will be as square as possible.
+    pub fn arrange_grid(&mut self, columns: Option<u32>) {
+        let n = self.scopes.len() as u32;
+        if n == 0 {
+            return;
+        }
+
+        let columns = columns
+            .unwrap_or_else(|| (n as f32).sqrt().ceil() as u32)
+            .max(1)
+            .min(n);
+        let rows = (n + columns - 1) / columns;
+
+        self.appearance.grid_columns = columns;
+        self.appearance.grid_rows = rows;
+
+        for (i, scope) in self.scopes.values_mut().enumerate() {
+            let i = i as u32;
+            scope.rect = GridRect {
+                x: i % columns,
+                y: i / columns,
+                w: 1,
+                h: 1,
+            };
+        }
+    }
+
+    /// Moves the given scopes into free cells of the grid, growing it towards
+    /// a square until they fit. Other scopes keep their place.
+    pub fn place_scopes(&mut self, names: &[String]) {
+        if names.is_empty() {
+            return;
+        }
+
+        // nothing to keep in place
+        if self.scopes.len() == names.len() {
+            self.arrange_grid(None);
+            return;
+        }
+
+        let taken = self
+            .scopes
+            .iter()
+            .filter(|(name, _)| !names.contains(name))
+            .map(|(_, scope)| scope.rect)
+            .collect::<Vec<_>>();
+        let is_free = |x: u32, y: u32| {
+            !taken
+                .iter()
+                .any(|r| x >= r.x && x < r.x + r.w && y >= r.y && y < r.y + r.h)
+        };
+
+        let free_cells = |columns: u32, rows: u32| {
+            (0..rows)
+                .flat_map(|y| (0..columns).map(move |x| (x, y)))
+                .filter(|&(x, y)| is_free(x, y))
+                .collect::<Vec<_>>()
+        };
+
+        let mut columns = self.appearance.grid_columns.max(1);
+        let mut rows = self.appearance.grid_rows.max(1);
+        let mut free = free_cells(columns, rows);
+        while free.len() < names.len() {
+            if columns <= rows {
+                columns += 1;
+            } else {
+                rows += 1;
+            }
+            free = free_cells(columns, rows);
+        }
+
+        self.appearance.grid_columns = columns;
+        self.appearance.grid_rows = rows;
+
+        for (name, (x, y)) in names.iter().zip(free) {
+            if let Some(scope) = self.scopes.get_mut(name) {
+                scope.rect = GridRect { x, y, w: 1, h: 1 };
+            }
+        }
     }
 
-    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), WriteError> {
+    /// Reloads any audio sources that changed on disk, returns whether
+    /// anything was reloaded
+    pub fn reload_changed_sources(&mut self) -> bool {
+        let sp = tracing::debug_span!("reload_sources");
+        let _e = sp.enter();
+
+        let mut reloaded = false;
+        for source in self.audio_sources.iter_mut() {
+            if source.changed_on_disk() {
+                tracing::info!("Reloading {}", source.path.display());
+                if let Err(e) = source.reload() {
+                    tracing::warn!("{}", e);
+                }
+                reloaded = true;
+            }
+        }
+
+        if reloaded {
+            self.configure_mixers();
+        }
+
+        reloaded
+    }
+
+    /// Marks the project as modified, the change is recorded in the undo
+    /// history on the next call to `commit_history`
+    pub fn mark_changed(&mut self) {
+        self.dirty = true;
+        self.history.pending = true;
+    }
+
+    fn snapshot(&self) -> Option<String> {
+        match serde_yaml::to_string(self) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                tracing::warn!("Failed to snapshot project: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn reset_history(&mut self) {
+        self.history = History {
+            current: self.snapshot(),
+            generation: self.history.generation + 1,
+            ..Default::default()
+        };
+    }
+
+    /// Records pending changes as a single undo step. Calling this only once
+    /// an edit is finished (e.g. a slider is released) coalesces it.
+    pub fn commit_history(&mut self) {
+        const MAX_UNDO: usize = 100;
+
+        if self.history.current.is_none() {
+            self.reset_history();
+            return;
+        }
+        if !self.history.pending {
+            return;
+        }
+        self.history.pending = false;
+
+        let snapshot = self.snapshot();
+        if snapshot.is_some() && snapshot != self.history.current {
+            self.history.generation += 1;
+            if let Some(previous) = std::mem::replace(&mut self.history.current, snapshot) {
+                self.history.undo.push(previous);
+                if self.history.undo.len() > MAX_UNDO {
+                    self.history.undo.remove(0);
+                }
+            }
+            self.history.redo.clear();
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.commit_history();
+        match self.history.undo.pop() {
+            Some(snapshot) => {
+                if let Some(current) = self.history.current.take() {
+                    self.history.redo.push(current);
+                }
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.commit_history();
+        match self.history.redo.pop() {
+            Some(snapshot) => {
+                if let Some(current) = self.history.current.take() {
+                    self.history.undo.push(current);
+                }
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn restore(&mut self, snapshot: String) {
+        let sp = tracing::debug_span!("restore_snapshot");
+        let _e = sp.enter();
+
+        let restored: State = match serde_yaml::from_str(&snapshot) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("Failed to restore project snapshot: {}", e);
+                return;
+            }
+        };
+
+        // reuse already loaded files
+        let mut old_sources = std::mem::take(&mut self.audio_sources);
+        self.audio_sources = restored.audio_sources;
+        for source in self.audio_sources.iter_mut() {
+            match old_sources.iter().position(|s| s.path == source.path) {
+                Some(i) => source.take_reader(old_sources.remove(i)),
+                None => {
+                    if let Err(e) = source.load() {
+                        tracing::warn!("{}", e);
+                    }
+                }
+            }
+        }
+
+        self.scopes = restored.scopes;
+        self.appearance = restored.appearance;
+        self.absolute_paths = restored.absolute_paths;
+
+        // the capture device stays open
+        let live_rate = self.live_input.as_ref().and_then(|l| l.sample_rate);
+        self.live_input = restored.live_input;
+        if let Some(live) = &mut self.live_input {
+            live.sample_rate = live_rate;
+        }
+        self.configure_mixers();
+
+        self.history.current = Some(snapshot);
+        self.history.generation += 1;
+        self.dirty = true;
+    }
+
+    pub fn write<P: AsRef<Path>>(&mut self, path: P) -> Result<(), WriteError> {
+        self.write_with_paths(path, self.absolute_paths)
+    }
+
+    pub fn write_with_paths<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        absolute_paths: bool,
+    ) -> Result<(), WriteError> {
         let sp = tracing::info_span!("save_project");
         let _e = sp.enter();
 
         let path = path.as_ref();
+        let project_dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        }
+        .canonicalize()
+        .context(DirError)?;
+
         let file = fs::File::create(path).context(CreateError {
             path: path.to_path_buf(),
         })?;
 
-        serde_yaml::to_writer(file, self).context(SerializeError)?;
+        // temporarily swap in the paths that should be stored
+        let source_paths = self
+            .audio_sources
+            .iter_mut()
+            .map(|source| {
+                let relative = if absolute_paths {
+                    None
+                } else {
+                    relative_path(&source.path, &project_dir)
+                };
+                let stored = relative.unwrap_or_else(|| source.path.clone());
+                std::mem::replace(&mut source.path, stored)
+            })
+            .collect::<Vec<_>>();
+
+        let res = serde_yaml::to_writer(file, self).context(SerializeError);
+
+        for (source, path) in self.audio_sources.iter_mut().zip(source_paths) {
+            source.path = path;
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    // a project as saved before format versions existed
+    fn v0_project() -> String {
+        let text = serde_yaml::to_string(&State::default()).unwrap();
+        let text = text
+            .lines()
+            .filter(|line| !line.starts_with("version:"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(!text.contains("version:"));
+        text
+    }
+
+    #[test]
+    fn migrates_v0_project() {
+        let path = test_util::write_file("v0", "rprj", &v0_project());
+
+        let (state, warnings) = State::from_file(&path).unwrap();
+        assert_eq!(state.version, CURRENT_VERSION);
+        assert!(warnings.is_empty());
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn v0_schema_errors_point_at_the_file() {
+        let text = v0_project().replace("absolute_paths: false", "absolute_paths: sometimes");
+        let line = text
+            .lines()
+            .position(|line| line.starts_with("absolute_paths:"))
+            .unwrap()
+            + 1;
+        let path = test_util::write_file("v0-invalid", "rprj", &text);
 
-        Ok(())
+        match State::from_file(&path) {
+            Err(ReadError::SchemaError { version, source }) => {
+                assert_eq!(version, 0);
+                let message = source.to_string();
+                assert!(
+                    message.contains(&format!("line {} ", line)),
+                    "{} does not point at line {}",
+                    message,
+                    line
+                );
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("invalid project was loaded"),
+        }
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn unchanged_migration() {
+        let mut doc: serde_yaml::Value = serde_yaml::from_str(&v0_project()).unwrap();
+        let migrated = migrate::migrate(&mut doc).unwrap();
+        assert_eq!(migrated.version, 0);
+        assert!(!migrated.changed);
+        assert_eq!(
+            doc.get("version").and_then(|v| v.as_u64()),
+            Some(u64::from(CURRENT_VERSION))
+        );
     }
 }
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Frames per second as an exact ratio, so that NTSC rates like 30000/1001
+/// don't drift
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Framerate {
+    num: u32,
+    den: u32,
+}
+
+impl Framerate {
+    pub const PRESETS: [Framerate; 10] = [
+        Framerate::ntsc(24),
+        Framerate::whole(24),
+        Framerate::whole(25),
+        Framerate::ntsc(30),
+        Framerate::whole(30),
+        Framerate::whole(50),
+        Framerate::ntsc(60),
+        Framerate::whole(60),
+        Framerate::whole(120),
+        Framerate::whole(144),
+    ];
+
+    pub fn new(num: u32, den: u32) -> Option<Self> {
+        if num == 0 || den == 0 {
+            None
+        } else {
+            Some(Framerate { num, den })
+        }
+    }
+
+    pub const fn whole(fps: u32) -> Self {
+        Framerate { num: fps, den: 1 }
+    }
+
+    /// The NTSC rate just below `fps`, e.g. 29.97 for 30
+    pub const fn ntsc(fps: u32) -> Self {
+        Framerate {
+            num: fps * 1000,
+            den: 1001,
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        f64::from(self.num) / f64::from(self.den)
+    }
+
+    pub fn frame_duration(self) -> Duration {
+        Duration::from_nanos(1_000_000_000 * u64::from(self.den) / u64::from(self.num))
+    }
+
+    /// Length of a frame in secs
+    pub fn frame_secs(self) -> f64 {
+        f64::from(self.den) / f64::from(self.num)
+    }
+
+    pub fn frame_to_secs(self, frame: f64) -> f64 {
+        frame * self.frame_secs()
+    }
+
+    pub fn secs_to_frame(self, secs: f64) -> f64 {
+        secs * self.as_f64()
+    }
+
+    /// First audio frame at `sample_rate` that belongs to `frame`
+    pub fn frame_start(self, frame: u64, sample_rate: u32) -> u64 {
+        let samples = u128::from(frame) * u128::from(sample_rate) * u128::from(self.den)
+            / u128::from(self.num);
+        samples as u64
+    }
+
+    /// Number of audio frames at `sample_rate` that belong to `frame`, varies
+    /// between frames if the rates don't divide evenly
+    pub fn samples_in_frame(self, frame: u64, sample_rate: u32) -> u64 {
+        self.frame_start(frame + 1, sample_rate) - self.frame_start(frame, sample_rate)
+    }
+
+    /// Audio frame at `sample_rate` for a fractional video frame position
+    pub fn position_to_samples(self, position: f64, sample_rate: u32) -> u64 {
+        let position = position.max(0.0);
+        let whole = position.floor() as u64;
+        let fract = position.fract() * self.samples_in_frame(whole, sample_rate) as f64;
+        self.frame_start(whole, sample_rate) + fract as u64
+    }
+}
+
+impl Default for Framerate {
+    fn default() -> Self {
+        Framerate::whole(60)
+    }
+}
+
+impl fmt::Display for Framerate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.num % self.den == 0 {
+            write!(f, "{}", self.num / self.den)
+        } else {
+            let fps = format!("{:.3}", self.as_f64());
+            write!(f, "{}", fps.trim_end_matches('0'))
+        }
+    }
+}
+
+impl FromStr for Framerate {
+    type Err = String;
+
+    /// Accepts "60", "30000/1001" and decimals like "29.97"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid framerate \"{}\"", s);
+
+        if let Some(slash) = s.find('/') {
+            let num = s[..slash].trim().parse().map_err(|_| invalid())?;
+            let den = s[slash + 1..].trim().parse().map_err(|_| invalid())?;
+            return Framerate::new(num, den).ok_or_else(invalid);
+        }
+
+        let fps = s.parse::<f64>().map_err(|_| invalid())?;
+        if !(fps > 0.0 && fps < f64::from(u32::MAX / 1001)) {
+            return Err(invalid());
+        }
+        if fps.fract() == 0.0 {
+            return Ok(Framerate::whole(fps as u32));
+        }
+
+        // decimals are usually rounded NTSC rates
+        let ntsc = (fps * 1.001).round();
+        let (num, den) = if (fps - ntsc / 1.001).abs() < 0.01 {
+            (ntsc as u32 * 1000, 1001)
+        } else {
+            ((fps * 1000.0).round() as u32, 1000)
+        };
+        // rates too small to represent round down to 0
+        Framerate::new(num, den).ok_or_else(invalid)
+    }
+}
+
+// whole rates are stored as plain numbers, like older projects did
+impl Serialize for Framerate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.den == 1 {
+            serializer.serialize_u32(self.num)
+        } else {
+            serializer.serialize_str(&format!("{}/{}", self.num, self.den))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Framerate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Whole(u32),
+            Decimal(f64),
+            Text(String),
+        }
+
+        let parsed = match Repr::deserialize(deserializer)? {
+            Repr::Whole(fps) => Framerate::new(fps, 1).ok_or_else(|| "framerate is 0".to_string()),
+            Repr::Decimal(fps) => fps.to_string().parse(),
+            Repr::Text(s) => s.parse(),
+        };
+        parsed.map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!("60".parse(), Ok(Framerate::whole(60)));
+        assert_eq!("30000/1001".parse(), Ok(Framerate::ntsc(30)));
+        assert_eq!("29.97".parse(), Ok(Framerate::ntsc(30)));
+        assert_eq!("12.5".parse::<Framerate>().map(Framerate::as_f64), Ok(12.5));
+    }
+
+    #[test]
+    fn rejects_zero_rates() {
+        for s in &["0", "0.0", "0.001", "0.0004", "-1", "0/1", "1/0", "nan"] {
+            assert!(s.parse::<Framerate>().is_err(), "{} was accepted", s);
+        }
+    }
+}
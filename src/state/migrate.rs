@@ -0,0 +1,80 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::state::ReadError;
+
+pub const CURRENT_VERSION: u32 = 2;
+
+type Migration = fn(&mut Mapping) -> Result<(), String>;
+
+// MIGRATIONS[n] upgrades a project from version n to version n + 1
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+// unversioned projects only lack fields that have serde defaults
+fn v0_to_v1(_project: &mut Mapping) -> Result<(), String> {
+    Ok(())
+}
+
+// framerates can now be fractional, whole framerates are still plain numbers
+fn v1_to_v2(_project: &mut Mapping) -> Result<(), String> {
+    Ok(())
+}
+
+fn version_key() -> Value {
+    Value::from("version")
+}
+
+pub struct Migrated {
+    /// Version the project was saved with
+    pub version: u32,
+    /// Whether a migration changed anything besides the version number
+    pub changed: bool,
+}
+
+/// Upgrades a project document to the current version in place
+pub fn migrate(doc: &mut Value) -> Result<Migrated, ReadError> {
+    let project = match doc.as_mapping_mut() {
+        Some(m) => m,
+        None => {
+            return Err(ReadError::MigrationError {
+                version: 0,
+                message: "project is not a mapping".to_string(),
+            })
+        }
+    };
+
+    let version = match project.get(&version_key()) {
+        None => 0,
+        Some(v) => match v.as_u64() {
+            Some(v) if v <= u64::from(CURRENT_VERSION) => v as u32,
+            Some(v) => {
+                return Err(ReadError::UnsupportedVersion {
+                    version: v,
+                    supported: CURRENT_VERSION,
+                })
+            }
+            None => {
+                return Err(ReadError::MigrationError {
+                    version: 0,
+                    message: format!("invalid version {:?}", v),
+                })
+            }
+        },
+    };
+
+    let mut original = project.clone();
+    original.remove(&version_key());
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let sp = tracing::debug_span!("migrate", from = from);
+        let _e = sp.enter();
+
+        migration(&mut *project).map_err(|message| ReadError::MigrationError {
+            version: from as u32,
+            message,
+        })?;
+    }
+    let changed = *project != original;
+    project.insert(version_key(), Value::from(CURRENT_VERSION));
+
+    Ok(Migrated { version, changed })
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the tests
+
+use std::fs;
+use std::path::PathBuf;
+
+/// File in the temp dir that no other test run uses
+pub fn temp_path(name: &str, ext: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "rawrscope-test-{}-{}.{}",
+        std::process::id(),
+        name,
+        ext
+    ))
+}
+
+/// Writes interleaved samples to a 32 bit float WAV file
+pub fn write_wav(
+    name: &str,
+    channels: u16,
+    sample_rate: u32,
+    samples: impl IntoIterator<Item = f32>,
+) -> PathBuf {
+    let path = temp_path(name, "wav");
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+    path
+}
+
+/// Writes a text file, e.g. a project
+pub fn write_file(name: &str, ext: &str, text: &str) -> PathBuf {
+    let path = temp_path(name, ext);
+    fs::write(&path, text).unwrap();
+    path
+}
@@ -1,16 +1,28 @@
-use crate::state::State;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::state::{Framerate, PlaybackState, State};
 
 use bitflags::bitflags;
 use imgui::{im_str, Ui};
 use tinyfiledialogs as tfd;
 
+use crate::audio::{envelope::Curve, source::AudioSource};
 use crate::scope::centering::{self, Algorithm};
+use shortcuts::{Action, Shortcuts};
+
+pub mod audio_settings;
+pub mod log;
+pub mod messages;
+pub mod shortcuts;
+pub mod timeline;
 
 bitflags! {
     #[derive(Default)]
     pub struct ExternalEvents: u32 {
         const REBUILD_MASTER = 0b00000001;
         const REDRAW_SCOPES = 0b00000010;
+        const REBUILD_PLAYER = 0b00000100;
     }
 }
 
@@ -20,6 +32,19 @@ fn view_toggle(v: &mut bool, text: &imgui::ImStr, ui: &imgui::Ui) {
     }
 }
 
+fn action_item(
+    label: &imgui::ImStr,
+    action: Action,
+    enabled: bool,
+    shortcuts: &Shortcuts,
+    ui: &imgui::Ui,
+) -> bool {
+    imgui::MenuItem::new(label)
+        .shortcut(&shortcuts.label(action))
+        .enabled(enabled)
+        .build(ui)
+}
+
 fn ms_slider(label: &str, value: &mut f32, ui: &imgui::Ui) -> bool {
     let mut ms = *value * 1000.;
     let changed = imgui::DragFloat::new(ui, &im_str!("{}", label), &mut ms)
@@ -31,6 +56,181 @@ fn ms_slider(label: &str, value: &mut f32, ui: &imgui::Ui) -> bool {
     changed
 }
 
+/// Asks before throwing away unsaved changes, returns whether to continue
+pub fn confirm_discard(state: &State) -> bool {
+    !state.dirty
+        || tfd::message_box_yes_no(
+            "Unsaved Changes",
+            "The current project has unsaved changes. Discard them?",
+            tfd::MessageBoxIcon::Warning,
+            tfd::YesNo::No,
+        ) == tfd::YesNo::Yes
+}
+
+// replaces the project while keeping ui state (open windows, messages)
+fn replace_project(state: &mut State, new_state: State, ext_events: &mut ExternalEvents) {
+    let ui_state = std::mem::take(&mut state.ui);
+    *state = new_state;
+    state.ui = ui_state;
+    *ext_events |= ExternalEvents::REBUILD_MASTER | ExternalEvents::REDRAW_SCOPES;
+}
+
+fn open_project(
+    state: &mut State,
+    config: &mut Config,
+    path: &Path,
+    ext_events: &mut ExternalEvents,
+) {
+    match State::from_file(path) {
+        Ok((new_state, warnings)) => {
+            replace_project(state, new_state, ext_events);
+            for w in warnings {
+                state.ui.messages.warn(w.to_string());
+            }
+            state
+                .ui
+                .messages
+                .info(format!("Opened {}", state.file_path.display()));
+            config.add_recent_project(&state.file_path);
+        }
+        Err(e) => {
+            state.ui.messages.error(e.to_string());
+        }
+    }
+}
+
+fn save_project(state: &mut State, config: &mut Config, path: &Path) {
+    match state.write(path) {
+        Ok(()) => {
+            state.file_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            state.dirty = false;
+            state
+                .ui
+                .messages
+                .info(format!("Saved {}", state.file_path.display()));
+            config.add_recent_project(&state.file_path);
+        }
+        Err(e) => {
+            state.ui.messages.error(e.to_string());
+        }
+    }
+}
+
+fn save_project_as(state: &mut State, config: &mut Config) {
+    let default_path = if state.file_path.as_os_str().is_empty() {
+        "untitled.rprj".to_string()
+    } else {
+        state.file_path.to_string_lossy().into_owned()
+    };
+
+    if let Some(path) = tfd::save_file_dialog_with_filter(
+        "Save Project As...",
+        &default_path,
+        &["*.rprj"],
+        "rawrscope projects",
+    ) {
+        let mut path = PathBuf::from(path);
+        if path.extension().is_none() {
+            path.set_extension("rprj");
+        }
+        save_project(state, config, &path);
+    }
+}
+
+fn format_time(secs: f32) -> String {
+    let millis = (secs.max(0.0) * 1000.0) as u32;
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn optional_secs(label: &str, value: &mut Option<f32>, default: f32, ui: &imgui::Ui) -> bool {
+    let mut enabled = value.is_some();
+    let mut changed = ui.checkbox(&im_str!("##{}", label), &mut enabled);
+    ui.same_line(0.0);
+
+    let mut secs = value.unwrap_or(default);
+    if imgui::DragFloat::new(ui, &im_str!("{}", label), &mut secs)
+        .min(0.0)
+        .max(3600.0)
+        .speed(0.01)
+        .display_format(im_str!("%.3f s"))
+        .build()
+    {
+        enabled = true;
+        changed = true;
+    }
+
+    *value = if enabled { Some(secs) } else { None };
+    changed
+}
+
+fn curve_combo(label: &str, curve: &mut Curve, ui: &imgui::Ui) -> bool {
+    let mut changed = false;
+    imgui::ComboBox::new(&im_str!("{}", label))
+        .preview_value(&im_str!("{}", curve))
+        .build(ui, || {
+            for c in Curve::ALL.iter() {
+                if imgui::Selectable::new(&im_str!("{}", c))
+                    .selected(*c == *curve)
+                    .build(ui)
+                {
+                    *curve = *c;
+                    changed = true;
+                }
+            }
+        });
+    changed
+}
+
+fn source_editor(source: &mut AudioSource, ui: &imgui::Ui) -> bool {
+    let mut changed = false;
+
+    match source.wav_reader.as_ref() {
+        Some(reader) => {
+            let spec = reader.spec();
+            ui.text(im_str!(
+                "{} Hz, {} ch, {} bit, {}",
+                spec.sample_rate,
+                spec.channels,
+                spec.bits_per_sample,
+                format_time(reader.duration() as f32 / spec.sample_rate as f32)
+            ));
+        }
+        None => ui.text_colored([1.0, 0.4, 0.4, 1.0], im_str!("File missing or unreadable")),
+    }
+
+    ui.text("Trim");
+    changed |= optional_secs("Start", &mut source.start, 0.0, ui);
+    changed |= optional_secs("End", &mut source.end, 0.0, ui);
+    changed |= imgui::DragFloat::new(ui, im_str!("Offset"), &mut source.offset)
+        .min(-3600.0)
+        .max(3600.0)
+        .speed(0.001)
+        .display_format(im_str!("%.3f s"))
+        .build();
+
+    ui.spacing();
+
+    ui.text("Fades");
+    changed |= optional_secs("Fade In", &mut source.fade_in, 1.0, ui);
+    changed |= curve_combo("Fade In Curve", &mut source.fade_in_curve, ui);
+    changed |= optional_secs("Fade Out", &mut source.fade_out, 1.0, ui);
+    changed |= curve_combo("Fade Out Curve", &mut source.fade_out_curve, ui);
+
+    ui.spacing();
+
+    ui.text("Connections");
+    for conn in &source.connections {
+        ui.bullet_text(&im_str!("channel {} -> {:?}", conn.channel, conn.target));
+    }
+
+    changed
+}
+
 fn scope_editor(scope: &mut crate::scope::Scope, ui: &imgui::Ui) -> bool {
     let mut changed = false;
 
@@ -85,37 +285,175 @@ fn scope_editor(scope: &mut crate::scope::Scope, ui: &imgui::Ui) -> bool {
         });
     changed |= scope.centering.ui(ui);
 
+    ui.spacing();
+
+    ui.text("Filters");
+    let mut removed = None;
+    for (i, stage) in scope.filters.iter_mut().enumerate() {
+        let id = ui.push_id(i as i32);
+        ui.text(im_str!("{}", stage));
+        ui.same_line(0.0);
+        if ui.small_button(im_str!("Remove")) {
+            removed = Some(i);
+        }
+        changed |= stage.ui(ui);
+        id.pop(ui);
+    }
+    if let Some(i) = removed {
+        scope.filters.remove(i);
+        changed = true;
+    }
+    imgui::ComboBox::new(&im_str!("Add Filter"))
+        .preview_value(&im_str!(""))
+        .build(ui, || {
+            for stage in crate::scope::filter::Stage::defaults().iter() {
+                if imgui::Selectable::new(&im_str!("{}", stage)).build(ui) {
+                    scope.filters.push(stage.clone());
+                    changed = true;
+                }
+            }
+        });
+
     changed
 }
 
-pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut ExternalEvents) {
+pub fn ui<'a, 'ui>(
+    state: &'a mut State,
+    config: &'a mut Config,
+    shortcuts: &'a Shortcuts,
+    ui: &'a Ui<'ui>,
+    ext_events: &'a mut ExternalEvents,
+) {
+    let has_path = !state.file_path.as_os_str().is_empty();
+
+    let mut actions = shortcuts.pressed(ui);
+
     ui.main_menu_bar(|| {
         ui.menu(im_str!("File"), true, || {
-            if imgui::MenuItem::new(im_str!("Open")).build(ui) {
-                if let Some(path) = tfd::open_file_dialog(
-                    "Open Project...",
-                    ".",
-                    Some((&["*.rprj"], "rawrscope projects")),
-                ) {
-                    // TODO do not panic and log warnings
-                    *state = State::from_file(path).expect("could not load project").0;
-                    *ext_events |= ExternalEvents::REBUILD_MASTER | ExternalEvents::REDRAW_SCOPES;
+            for (label, action) in [
+                (im_str!("New"), Action::New),
+                (im_str!("Open..."), Action::Open),
+            ]
+            .iter()
+            {
+                if action_item(label, *action, true, shortcuts, ui) {
+                    actions.push(*action);
                 }
             }
 
-            if imgui::MenuItem::new(im_str!("Save"))
-                .enabled(!state.file_path.as_os_str().is_empty())
+            let mut recent_choice = None;
+            ui.menu(
+                im_str!("Open Recent"),
+                !config.files.recent.is_empty(),
+                || {
+                    for path in &config.files.recent {
+                        if imgui::MenuItem::new(&im_str!("{}", path.display())).build(ui) {
+                            recent_choice = Some(path.clone());
+                        }
+                    }
+                },
+            );
+            if let Some(path) = recent_choice {
+                if confirm_discard(state) {
+                    open_project(state, config, &path, ext_events);
+                }
+            }
+
+            ui.separator();
+
+            for (label, action) in [
+                (im_str!("Save"), Action::Save),
+                (im_str!("Save As..."), Action::SaveAs),
+            ]
+            .iter()
+            {
+                if action_item(label, *action, true, shortcuts, ui) {
+                    actions.push(*action);
+                }
+            }
+
+            if imgui::MenuItem::new(im_str!("Revert"))
+                .enabled(has_path && state.dirty)
+                .build(ui)
+                && confirm_discard(state)
+            {
+                let path = state.file_path.clone();
+                open_project(state, config, &path, ext_events);
+            }
+
+            ui.separator();
+            if imgui::MenuItem::new(im_str!("Store Absolute Paths"))
+                .selected(state.absolute_paths)
                 .build(ui)
             {
-                // TODO do not panic
-                state
-                    .write(&state.file_path)
-                    .expect("could not save project");
+                state.absolute_paths = !state.absolute_paths;
+                state.mark_changed();
+            }
+        });
+        ui.menu(im_str!("Edit"), true, || {
+            if action_item(
+                im_str!("Undo"),
+                Action::Undo,
+                state.history.can_undo(),
+                shortcuts,
+                ui,
+            ) {
+                actions.push(Action::Undo);
+            }
+            if action_item(
+                im_str!("Redo"),
+                Action::Redo,
+                state.history.can_redo(),
+                shortcuts,
+                ui,
+            ) {
+                actions.push(Action::Redo);
             }
         });
         ui.menu(im_str!("View"), true, || {
-            view_toggle(&mut state.ui.show_main, im_str!("Main Window"), ui);
-            view_toggle(&mut state.ui.show_scopes, im_str!("Scope Properties"), ui);
+            for (label, action, shown) in [
+                (
+                    im_str!("Main Window"),
+                    Action::ToggleMain,
+                    state.ui.show_main,
+                ),
+                (
+                    im_str!("Scope Properties"),
+                    Action::ToggleScopes,
+                    state.ui.show_scopes,
+                ),
+                (
+                    im_str!("Audio Sources"),
+                    Action::ToggleSources,
+                    state.ui.show_sources,
+                ),
+                (
+                    im_str!("Timeline"),
+                    Action::ToggleTimeline,
+                    state.ui.show_timeline,
+                ),
+                (
+                    im_str!("Messages"),
+                    Action::ToggleMessages,
+                    state.ui.show_messages,
+                ),
+                (im_str!("Log"), Action::ToggleLog, state.ui.show_log),
+            ]
+            .iter()
+            {
+                if imgui::MenuItem::new(label)
+                    .shortcut(&shortcuts.label(*action))
+                    .selected(*shown)
+                    .build(ui)
+                {
+                    actions.push(*action);
+                }
+            }
+            view_toggle(
+                &mut state.ui.show_audio_settings,
+                im_str!("Audio Settings"),
+                ui,
+            );
             view_toggle(
                 &mut state.ui.show_debug,
                 im_str!("Experimental Options"),
@@ -124,12 +462,80 @@ pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut Ex
         });
     });
 
+    let mut undo = false;
+    let mut redo = false;
+    for action in actions {
+        let playback = &mut state.playback;
+        match action {
+            Action::New => {
+                if confirm_discard(state) {
+                    replace_project(state, State::default(), ext_events);
+                }
+            }
+            Action::Open => {
+                if confirm_discard(state) {
+                    if let Some(path) = tfd::open_file_dialog(
+                        "Open Project...",
+                        ".",
+                        Some((&["*.rprj"], "rawrscope projects")),
+                    ) {
+                        open_project(state, config, Path::new(&path), ext_events);
+                    }
+                }
+            }
+            Action::Save => {
+                if has_path {
+                    let path = state.file_path.clone();
+                    save_project(state, config, &path);
+                } else {
+                    save_project_as(state, config);
+                }
+            }
+            Action::SaveAs => save_project_as(state, config),
+            Action::Undo => undo = true,
+            Action::Redo => redo = true,
+            Action::PlayPause => playback.playing = !playback.playing,
+            Action::SeekForward => playback.seek(playback.frame.saturating_add(100)),
+            Action::SeekBackward => playback.seek(playback.frame.saturating_sub(100)),
+            Action::SeekStart => playback.seek(0),
+            Action::StepForward => playback.step(1),
+            Action::StepBackward => playback.step(-1),
+            Action::LoopIn => playback.loop_in = Some(playback.frame),
+            Action::LoopOut => playback.loop_out = Some(playback.frame),
+            Action::ToggleLoop => playback.looping = !playback.looping,
+            Action::ToggleMain => state.ui.show_main = !state.ui.show_main,
+            Action::ToggleScopes => state.ui.show_scopes = !state.ui.show_scopes,
+            Action::ToggleSources => state.ui.show_sources = !state.ui.show_sources,
+            Action::ToggleTimeline => state.ui.show_timeline = !state.ui.show_timeline,
+            Action::ToggleMessages => state.ui.show_messages = !state.ui.show_messages,
+            Action::ToggleLog => state.ui.show_log = !state.ui.show_log,
+        }
+
+        match action {
+            Action::SeekForward
+            | Action::SeekBackward
+            | Action::SeekStart
+            | Action::StepForward
+            | Action::StepBackward => *ext_events |= ExternalEvents::REDRAW_SCOPES,
+            _ => {}
+        }
+    }
+
     // FIXME fun borrow checker workaround, should probably not have one
     // unified state struct anymore
     let uistate = &mut state.ui;
     let playstate = &mut state.playback;
     let dbgstate = &mut state.debug;
     let scopes = &mut state.scopes;
+    let sources = &mut state.audio_sources;
+    let appearance = &mut state.appearance;
+    let messages = &mut uistate.messages;
+
+    // set when anything in the project was edited
+    let mut changed = false;
+
+    // set when scope mixers need to be reconfigured
+    let mut sources_changed = false;
 
     if uistate.show_main {
         imgui::Window::new(&im_str!(
@@ -137,7 +543,7 @@ pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut Ex
             clap::crate_version!(),
             git_version::git_version!()
         ))
-        .size([300.0, 90.0], imgui::Condition::Always)
+        .size([300.0, 140.0], imgui::Condition::Always)
         .resizable(false)
         .opened(&mut uistate.show_main)
         .build(&ui, || {
@@ -151,14 +557,58 @@ pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut Ex
             }
             ui.same_line(0.0);
             if ui.small_button(im_str!("+100 frames")) {
-                playstate.frame = playstate.frame.saturating_add(100);
+                playstate.seek(playstate.frame.saturating_add(100));
                 *ext_events |= ExternalEvents::REDRAW_SCOPES;
             }
             ui.same_line(0.0);
             if ui.small_button(im_str!("-100 frames")) {
-                playstate.frame = playstate.frame.saturating_sub(100);
+                playstate.seek(playstate.frame.saturating_sub(100));
                 *ext_events |= ExternalEvents::REDRAW_SCOPES;
             }
+
+            if ui.small_button(im_str!("<")) {
+                playstate.step(-1);
+                *ext_events |= ExternalEvents::REDRAW_SCOPES;
+            }
+            ui.same_line(0.0);
+            if ui.small_button(im_str!(">")) {
+                playstate.step(1);
+                *ext_events |= ExternalEvents::REDRAW_SCOPES;
+            }
+            ui.same_line(0.0);
+            let width = ui.push_item_width(80.0);
+            imgui::ComboBox::new(im_str!("Speed"))
+                .preview_value(&im_str!("{}x", playstate.speed))
+                .build(ui, || {
+                    for speed in PlaybackState::SPEEDS.iter() {
+                        if imgui::Selectable::new(&im_str!("{}x", speed))
+                            .selected(*speed == playstate.speed)
+                            .build(ui)
+                        {
+                            playstate.speed = *speed;
+                        }
+                    }
+                });
+
+            imgui::ComboBox::new(im_str!("Framerate"))
+                .preview_value(&im_str!("{} fps", appearance.framerate))
+                .build(ui, || {
+                    for framerate in Framerate::PRESETS.iter() {
+                        if imgui::Selectable::new(&im_str!("{} fps", framerate))
+                            .selected(*framerate == appearance.framerate)
+                            .build(ui)
+                        {
+                            // stay at the same point in time
+                            let secs = appearance.framerate.frame_to_secs(playstate.position());
+                            playstate.seek(framerate.secs_to_frame(secs) as u32);
+
+                            appearance.framerate = *framerate;
+                            changed = true;
+                            *ext_events |= ExternalEvents::REDRAW_SCOPES;
+                        }
+                    }
+                });
+            width.pop(ui);
         });
     }
 
@@ -172,14 +622,113 @@ pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut Ex
                     if imgui::CollapsingHeader::new(&im_str!("{}", name)).build(ui) {
                         let id = ui.push_id(name);
                         if scope_editor(scope, ui) {
+                            changed = true;
+                            *ext_events |= ExternalEvents::REDRAW_SCOPES;
+                        }
+                        id.pop(ui);
+                    }
+                }
+            });
+    }
+
+    if uistate.show_sources {
+        imgui::Window::new(im_str!("Audio Sources"))
+            .size([360.0, 500.0], imgui::Condition::FirstUseEver)
+            .opened(&mut uistate.show_sources)
+            .build(&ui, || {
+                if ui.small_button(im_str!("Add...")) {
+                    if let Some(paths) = tfd::open_file_dialog_multi(
+                        "Add Audio Sources...",
+                        ".",
+                        Some((&["*.wav"], "WAV files")),
+                    ) {
+                        for path in paths {
+                            let mut source = AudioSource::new(PathBuf::from(path));
+                            if let Err(e) = source.load() {
+                                messages.warn(e.to_string());
+                            }
+                            sources.push(source);
+                        }
+                        sources_changed = true;
+                    }
+                }
+
+                let mut removed = None;
+                for (i, source) in sources.iter_mut().enumerate() {
+                    let name = source
+                        .path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    if imgui::CollapsingHeader::new(&im_str!("{}##{}", name, i)).build(ui) {
+                        let id = ui.push_id(i as i32);
+
+                        ui.text_wrapped(&im_str!("{}", source.path.display()));
+                        if ui.small_button(im_str!("Relink...")) {
+                            if let Some(path) = tfd::open_file_dialog(
+                                "Relink Audio Source...",
+                                &source.path.to_string_lossy(),
+                                Some((&["*.wav"], "WAV files")),
+                            ) {
+                                source.path = PathBuf::from(path);
+                                if let Err(e) = source.reload() {
+                                    messages.warn(e.to_string());
+                                }
+                                sources_changed = true;
+                            }
+                        }
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("Remove")) {
+                            removed = Some(i);
+                        }
+
+                        if source_editor(source, ui) {
+                            changed = true;
                             *ext_events |= ExternalEvents::REDRAW_SCOPES;
                         }
+
                         id.pop(ui);
                     }
                 }
+                if let Some(i) = removed {
+                    sources.remove(i);
+                    sources_changed = true;
+                }
             });
     }
 
+    if uistate.show_timeline {
+        uistate.timeline.window(
+            ui,
+            &mut uistate.show_timeline,
+            sources,
+            playstate,
+            appearance.framerate,
+            ext_events,
+        );
+    } else {
+        playstate.scrubbing = false;
+    }
+
+    if uistate.show_messages {
+        messages.window(ui, &mut uistate.show_messages);
+    }
+
+    if uistate.show_log {
+        uistate.log.window(ui, &mut uistate.show_log);
+    }
+
+    if uistate.show_audio_settings
+        && uistate
+            .audio_settings
+            .window(ui, &mut uistate.show_audio_settings, &mut config.audio)
+    {
+        *ext_events |= ExternalEvents::REBUILD_PLAYER;
+        if let Err(e) = config.write() {
+            messages.warn(format!("Failed to save audio settings: {}", e));
+        }
+    }
+
     if uistate.show_debug {
         imgui::Window::new(im_str!("Experimental Options"))
             .size([250.0, 190.0], imgui::Condition::Always)
@@ -202,4 +751,24 @@ pub fn ui<'a, 'ui>(state: &'a mut State, ui: &'a Ui<'ui>, ext_events: &'a mut Ex
                     .build();
             });
     }
+
+    if sources_changed {
+        changed = true;
+        state.configure_mixers();
+        *ext_events |= ExternalEvents::REBUILD_MASTER | ExternalEvents::REDRAW_SCOPES;
+    }
+    if changed {
+        state.mark_changed();
+    }
+
+    if (undo && state.undo()) || (redo && state.redo()) {
+        *ext_events |= ExternalEvents::REBUILD_MASTER | ExternalEvents::REDRAW_SCOPES;
+    }
+
+    state.ui.messages.toasts(ui);
+
+    // wait until sliders are released so that drags become a single step
+    if !ui.is_any_item_active() {
+        state.commit_history();
+    }
 }
@@ -0,0 +1,268 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use imgui::{im_str, ImStr};
+use tinyfiledialogs as tfd;
+
+use crate::audio::playback;
+use crate::config::{self, OutputBackend};
+
+const BACKENDS: [(OutputBackend, &str); 3] = [
+    (OutputBackend::Device, "Audio Device"),
+    (OutputBackend::Null, "None"),
+    (OutputBackend::File, "WAV File"),
+];
+
+struct Device {
+    name: String,
+    sample_rates: Vec<u32>,
+}
+
+impl Device {
+    fn new(device: cpal::Device) -> Option<Self> {
+        Some(Device {
+            name: device.name().ok()?,
+            sample_rates: playback::supported_sample_rates(&device),
+        })
+    }
+}
+
+struct HostDevices {
+    name: String,
+    default_device: Option<Device>,
+    devices: Vec<Device>,
+}
+
+// hosts and their output devices, querying them every frame would be slow
+struct Devices {
+    default_host: String,
+    hosts: Vec<HostDevices>,
+}
+
+impl Devices {
+    fn query() -> Self {
+        let sp = tracing::debug_span!("query_audio_devices");
+        let _e = sp.enter();
+
+        let hosts = cpal::available_hosts()
+            .into_iter()
+            .map(|id| {
+                let name = format!("{:?}", id);
+                let host = match cpal::host_from_id(id) {
+                    Ok(host) => host,
+                    Err(e) => {
+                        tracing::warn!("Could not use host {:?}: {}", id, e);
+                        return HostDevices {
+                            name,
+                            default_device: None,
+                            devices: Vec::new(),
+                        };
+                    }
+                };
+                let devices = match host.output_devices() {
+                    Ok(devices) => devices.filter_map(Device::new).collect(),
+                    Err(e) => {
+                        tracing::warn!("Failed to query output devices of {:?}: {}", id, e);
+                        Vec::new()
+                    }
+                };
+                HostDevices {
+                    name,
+                    default_device: host.default_output_device().and_then(Device::new),
+                    devices,
+                }
+            })
+            .collect();
+
+        Devices {
+            default_host: format!("{:?}", cpal::default_host().id()),
+            hosts,
+        }
+    }
+
+    fn host(&self, name: Option<&String>) -> Option<&HostDevices> {
+        let name = name.unwrap_or(&self.default_host);
+        self.hosts.iter().find(|h| &h.name == name)
+    }
+
+    /// Rates the selected output supports
+    fn sample_rates(&self, audio: &config::Audio) -> Vec<u32> {
+        if audio.backend != OutputBackend::Device {
+            return playback::SAMPLE_RATES.to_vec();
+        }
+
+        let host = self.host(audio.host.as_ref());
+        let device = match &audio.device {
+            Some(name) => host.and_then(|h| h.devices.iter().find(|d| &d.name == name)),
+            None => host.and_then(|h| h.default_device.as_ref()),
+        };
+        device.map(|d| d.sample_rates.clone()).unwrap_or_default()
+    }
+}
+
+// combo with a "Default" entry for `None`
+fn optional_combo<T: Clone + PartialEq>(
+    label: &ImStr,
+    value: &mut Option<T>,
+    options: &[(T, String)],
+    ui: &imgui::Ui,
+) -> bool {
+    let preview = value
+        .as_ref()
+        .map(|v| {
+            options
+                .iter()
+                .find(|(o, _)| o == v)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_else(|| "Unavailable".to_string())
+        })
+        .unwrap_or_else(|| "Default".to_string());
+
+    let mut changed = false;
+    imgui::ComboBox::new(label)
+        .preview_value(&im_str!("{}", preview))
+        .build(ui, || {
+            if imgui::Selectable::new(im_str!("Default"))
+                .selected(value.is_none())
+                .build(ui)
+            {
+                changed |= value.is_some();
+                *value = None;
+            }
+            for (option, name) in options {
+                let selected = value.as_ref() == Some(option);
+                if imgui::Selectable::new(&im_str!("{}", name))
+                    .selected(selected)
+                    .build(ui)
+                    && !selected
+                {
+                    *value = Some(option.clone());
+                    changed = true;
+                }
+            }
+        });
+    changed
+}
+
+/// Output selection, applied as soon as it changes
+#[derive(Default)]
+pub struct AudioSettings {
+    devices: Option<Devices>,
+}
+
+impl AudioSettings {
+    /// Returns whether the output has to be reopened
+    pub fn window(&mut self, ui: &imgui::Ui, opened: &mut bool, audio: &mut config::Audio) -> bool {
+        let devices = self.devices.get_or_insert_with(Devices::query);
+        let mut refresh = false;
+        let mut changed = false;
+        // host, device or backend
+        let mut output_changed = false;
+
+        imgui::Window::new(im_str!("Audio Settings"))
+            .size([400.0, 200.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                let backend_name = BACKENDS
+                    .iter()
+                    .find(|(b, _)| *b == audio.backend)
+                    .map(|(_, name)| *name)
+                    .unwrap_or_default();
+                imgui::ComboBox::new(im_str!("Output"))
+                    .preview_value(&im_str!("{}", backend_name))
+                    .build(ui, || {
+                        for (backend, name) in BACKENDS.iter() {
+                            if imgui::Selectable::new(&im_str!("{}", name))
+                                .selected(*backend == audio.backend)
+                                .build(ui)
+                                && *backend != audio.backend
+                            {
+                                audio.backend = backend.clone();
+                                output_changed = true;
+                            }
+                        }
+                    });
+
+                match audio.backend {
+                    OutputBackend::Device => {
+                        let hosts = devices
+                            .hosts
+                            .iter()
+                            .map(|h| (h.name.clone(), h.name.clone()))
+                            .collect::<Vec<_>>();
+                        if optional_combo(im_str!("Host"), &mut audio.host, &hosts, ui) {
+                            // device names differ between hosts
+                            audio.device = None;
+                            output_changed = true;
+                        }
+
+                        let host_devices = devices
+                            .host(audio.host.as_ref())
+                            .map(|h| {
+                                h.devices
+                                    .iter()
+                                    .map(|d| (d.name.clone(), d.name.clone()))
+                                    .collect::<Vec<_>>()
+                            })
+                            .unwrap_or_default();
+                        output_changed |=
+                            optional_combo(im_str!("Device"), &mut audio.device, &host_devices, ui);
+
+                        if ui.small_button(im_str!("Refresh Devices")) {
+                            refresh = true;
+                        }
+                    }
+                    OutputBackend::File => {
+                        ui.text(im_str!("Writing to {}", audio.output_file.display()));
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("Browse...")) {
+                            if let Some(path) = tfd::save_file_dialog_with_filter(
+                                "Write Audio To...",
+                                &audio.output_file.to_string_lossy(),
+                                &["*.wav"],
+                                "WAV files",
+                            ) {
+                                audio.output_file = path.into();
+                                output_changed = true;
+                            }
+                        }
+                    }
+                    OutputBackend::Null => {}
+                }
+
+                ui.separator();
+
+                let supported_rates = devices.sample_rates(audio);
+                if output_changed {
+                    changed = true;
+                    // the new output would fall back to its default rate anyway
+                    if let Some(rate) = audio.sample_rate {
+                        if !supported_rates.contains(&rate) {
+                            audio.sample_rate = None;
+                        }
+                    }
+                }
+
+                let rates = supported_rates
+                    .iter()
+                    .map(|r| (*r, format!("{} Hz", r)))
+                    .collect::<Vec<_>>();
+                changed |=
+                    optional_combo(im_str!("Sample Rate"), &mut audio.sample_rate, &rates, ui);
+
+                // only apply once editing is done, reopening the device on
+                // every keystroke would stutter
+                if ui
+                    .input_float(im_str!("Buffer (ms)"), &mut audio.buffer_ms)
+                    .enter_returns_true(true)
+                    .build()
+                {
+                    audio.buffer_ms = audio.buffer_ms.max(1.0).min(1000.0);
+                    changed = true;
+                }
+            });
+
+        if refresh {
+            self.devices = None;
+        }
+        changed
+    }
+}
@@ -0,0 +1,144 @@
+use derivative::Derivative;
+use imgui::{im_str, ImString};
+
+use crate::logging::{self, Entry};
+
+const LEVELS: [tracing::Level; 5] = [
+    tracing::Level::ERROR,
+    tracing::Level::WARN,
+    tracing::Level::INFO,
+    tracing::Level::DEBUG,
+    tracing::Level::TRACE,
+];
+
+fn level_color(level: tracing::Level) -> [f32; 4] {
+    match level {
+        tracing::Level::ERROR => [1.0, 0.4, 0.4, 1.0],
+        tracing::Level::WARN => [1.0, 0.8, 0.3, 1.0],
+        tracing::Level::INFO => [1.0, 1.0, 1.0, 1.0],
+        _ => [0.6, 0.6, 0.6, 1.0],
+    }
+}
+
+/// Filter state of the log window
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct LogConsole {
+    #[derivative(Default(value = "tracing::Level::INFO"))]
+    level: tracing::Level,
+    span: Option<&'static str>,
+    #[derivative(Default(value = "ImString::with_capacity(128)"))]
+    search: ImString,
+    #[derivative(Default(value = "true"))]
+    auto_scroll: bool,
+}
+
+impl LogConsole {
+    fn matches(&self, entry: &Entry) -> bool {
+        let search = self.search.to_str();
+        entry.level <= self.level
+            && self.span.map(|s| entry.spans.contains(&s)).unwrap_or(true)
+            && (search.is_empty()
+                || entry.message.contains(search)
+                || entry.target.contains(search))
+    }
+
+    pub fn window(&mut self, ui: &imgui::Ui, opened: &mut bool) {
+        imgui::Window::new(im_str!("Log"))
+            .size([700.0, 300.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                // copy what is shown, the log can't be written to while it's
+                // locked
+                let (entries, span_names) = logging::with_buffer(|buffer| {
+                    let entries = buffer
+                        .entries
+                        .iter()
+                        .filter(|e| self.matches(e))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    (entries, buffer.span_names.clone())
+                });
+
+                let width = ui.push_item_width(100.0);
+                imgui::ComboBox::new(im_str!("Level"))
+                    .preview_value(&im_str!("{}", self.level))
+                    .build(ui, || {
+                        for level in LEVELS.iter() {
+                            if imgui::Selectable::new(&im_str!("{}", level))
+                                .selected(*level == self.level)
+                                .build(ui)
+                            {
+                                self.level = *level;
+                            }
+                        }
+                    });
+                ui.same_line(0.0);
+
+                let span_preview = im_str!("{}", self.span.unwrap_or("all"));
+                imgui::ComboBox::new(im_str!("Span"))
+                    .preview_value(&span_preview)
+                    .build(ui, || {
+                        if imgui::Selectable::new(im_str!("all"))
+                            .selected(self.span.is_none())
+                            .build(ui)
+                        {
+                            self.span = None;
+                        }
+                        for name in span_names.iter() {
+                            if imgui::Selectable::new(&im_str!("{}", name))
+                                .selected(self.span == Some(*name))
+                                .build(ui)
+                            {
+                                self.span = Some(*name);
+                            }
+                        }
+                    });
+                ui.same_line(0.0);
+
+                width.pop(ui);
+                let width = ui.push_item_width(200.0);
+                ui.input_text(im_str!("Search"), &mut self.search).build();
+                width.pop(ui);
+                ui.same_line(0.0);
+
+                ui.checkbox(im_str!("Auto-scroll"), &mut self.auto_scroll);
+                ui.same_line(0.0);
+
+                if ui.small_button(im_str!("Copy")) {
+                    let text = entries
+                        .iter()
+                        .map(|e| e.to_string())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    ui.set_clipboard_text(&ImString::new(text));
+                }
+                ui.same_line(0.0);
+
+                if ui.small_button(im_str!("Clear")) {
+                    logging::with_buffer(|buffer| buffer.entries.clear());
+                }
+                ui.separator();
+
+                imgui::ChildWindow::new(im_str!("log entries"))
+                    .horizontal_scrollbar(true)
+                    .build(ui, || {
+                        // only draw the lines that are scrolled into view
+                        let line_height = ui.text_line_height_with_spacing();
+                        let [x, top] = ui.cursor_pos();
+                        let first = ((ui.scroll_y() - top) / line_height).max(0.0) as usize;
+                        let visible = (ui.window_size()[1] / line_height) as usize + 2;
+
+                        ui.set_cursor_pos([x, top + first as f32 * line_height]);
+                        for entry in entries.iter().skip(first).take(visible) {
+                            ui.text_colored(level_color(entry.level), &entry.to_string());
+                        }
+                        ui.set_cursor_pos([x, top + entries.len() as f32 * line_height]);
+
+                        if self.auto_scroll && ui.scroll_y() >= ui.scroll_max_y() {
+                            ui.set_scroll_here_y_with_ratio(1.0);
+                        }
+                    });
+            });
+    }
+}
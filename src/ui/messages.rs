@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use imgui::im_str;
+
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+const MAX_MESSAGES: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn color(self) -> [f32; 4] {
+        match self {
+            Level::Info => [1.0, 1.0, 1.0, 1.0],
+            Level::Warning => [1.0, 0.8, 0.3, 1.0],
+            Level::Error => [1.0, 0.4, 0.4, 1.0],
+        }
+    }
+}
+
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    pub time: Instant,
+}
+
+/// User-facing notifications, shown as toasts and kept in a log
+#[derive(Default)]
+pub struct Messages {
+    log: Vec<Message>,
+}
+
+impl Messages {
+    pub fn push<S: Into<String>>(&mut self, level: Level, text: S) {
+        let text = text.into();
+        match level {
+            Level::Info => tracing::info!("{}", text),
+            Level::Warning => tracing::warn!("{}", text),
+            Level::Error => tracing::error!("{}", text),
+        }
+
+        self.log.push(Message {
+            level,
+            text,
+            time: Instant::now(),
+        });
+        if self.log.len() > MAX_MESSAGES {
+            self.log.remove(0);
+        }
+    }
+
+    pub fn info<S: Into<String>>(&mut self, text: S) {
+        self.push(Level::Info, text);
+    }
+
+    pub fn warn<S: Into<String>>(&mut self, text: S) {
+        self.push(Level::Warning, text);
+    }
+
+    pub fn error<S: Into<String>>(&mut self, text: S) {
+        self.push(Level::Error, text);
+    }
+
+    /// Draws recent messages in the bottom right corner of the screen
+    pub fn toasts(&self, ui: &imgui::Ui) {
+        let [width, height] = ui.io().display_size;
+        let now = Instant::now();
+
+        let mut y = height - 10.0;
+        for (i, message) in self
+            .log
+            .iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, m)| now.duration_since(m.time) < TOAST_DURATION)
+        {
+            let mut toast_height = 0.0;
+            imgui::Window::new(&im_str!("##toast{}", i))
+                .position([width - 10.0, y], imgui::Condition::Always)
+                .position_pivot([1.0, 1.0])
+                .title_bar(false)
+                .resizable(false)
+                .movable(false)
+                .always_auto_resize(true)
+                .focus_on_appearing(false)
+                .save_settings(false)
+                .build(ui, || {
+                    ui.text_colored(message.level.color(), &message.text);
+                    toast_height = ui.window_size()[1];
+                });
+            y -= toast_height + 5.0;
+        }
+    }
+
+    pub fn window(&mut self, ui: &imgui::Ui, opened: &mut bool) {
+        let log = &mut self.log;
+        imgui::Window::new(im_str!("Messages"))
+            .size([500.0, 250.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                if ui.small_button(im_str!("Clear")) {
+                    log.clear();
+                }
+                ui.separator();
+
+                imgui::ChildWindow::new(im_str!("message log")).build(ui, || {
+                    for message in log.iter() {
+                        ui.text_colored(message.level.color(), &message.text);
+                    }
+                });
+            });
+    }
+}
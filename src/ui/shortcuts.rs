@@ -0,0 +1,193 @@
+use std::fmt;
+use std::str::FromStr;
+
+use imgui::ImString;
+use winit::event::VirtualKeyCode;
+
+use crate::config::Keybindings;
+
+// keys that can be bound, named like their `VirtualKeyCode` variant
+const KEYS: [VirtualKeyCode; 75] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+        Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10,
+        F11, F12, Escape, Insert, Delete, Home, End, PageUp, PageDown, Left, Right, Up, Down, Back,
+        Return, Space, Tab, Comma, Period, Minus, Equals, LBracket, RBracket, Semicolon,
+        Apostrophe, Slash, Backslash, Grave, Pause,
+    ]
+};
+
+fn key_name(key: VirtualKeyCode) -> String {
+    let name = format!("{:?}", key);
+    // "Key1" -> "1"
+    match name.strip_prefix("Key") {
+        Some(digit) if !digit.is_empty() => digit.to_string(),
+        _ => name,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    New,
+    Open,
+    Save,
+    SaveAs,
+    Undo,
+    Redo,
+    PlayPause,
+    SeekForward,
+    SeekBackward,
+    SeekStart,
+    StepForward,
+    StepBackward,
+    LoopIn,
+    LoopOut,
+    ToggleLoop,
+    ToggleMain,
+    ToggleScopes,
+    ToggleSources,
+    ToggleTimeline,
+    ToggleMessages,
+    ToggleLog,
+}
+
+/// A key together with the modifiers that have to be held, e.g. "Ctrl+S"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyCombo {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub key: VirtualKeyCode,
+}
+
+impl FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ctrl = false;
+        let mut shift = false;
+        let mut alt = false;
+        let mut key = None;
+
+        for part in s.split('+').map(str::trim) {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => ctrl = true,
+                "shift" => shift = true,
+                "alt" => alt = true,
+                _ if key.is_some() => {
+                    return Err(format!("\"{}\" has more than one key", s));
+                }
+                _ => {
+                    key = KEYS
+                        .iter()
+                        .find(|k| key_name(**k).eq_ignore_ascii_case(part))
+                        .copied();
+                    if key.is_none() {
+                        return Err(format!("Unknown key \"{}\" in \"{}\"", part, s));
+                    }
+                }
+            }
+        }
+
+        match key {
+            Some(key) => Ok(KeyCombo {
+                ctrl,
+                shift,
+                alt,
+                key,
+            }),
+            None => Err(format!("\"{}\" has no key", s)),
+        }
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", key_name(self.key))
+    }
+}
+
+/// Parsed keybindings from the config
+pub struct Shortcuts {
+    bindings: Vec<(Action, KeyCombo)>,
+}
+
+impl Shortcuts {
+    pub fn new(keybindings: &Keybindings) -> Self {
+        let kb = keybindings;
+        let configured = [
+            (Action::New, &kb.new),
+            (Action::Open, &kb.open),
+            (Action::Save, &kb.save),
+            (Action::SaveAs, &kb.save_as),
+            (Action::Undo, &kb.undo),
+            (Action::Redo, &kb.redo),
+            (Action::PlayPause, &kb.play_pause),
+            (Action::SeekForward, &kb.seek_forward),
+            (Action::SeekBackward, &kb.seek_backward),
+            (Action::SeekStart, &kb.seek_start),
+            (Action::StepForward, &kb.step_forward),
+            (Action::StepBackward, &kb.step_backward),
+            (Action::LoopIn, &kb.loop_in),
+            (Action::LoopOut, &kb.loop_out),
+            (Action::ToggleLoop, &kb.toggle_loop),
+            (Action::ToggleMain, &kb.toggle_main),
+            (Action::ToggleScopes, &kb.toggle_scopes),
+            (Action::ToggleSources, &kb.toggle_sources),
+            (Action::ToggleTimeline, &kb.toggle_timeline),
+            (Action::ToggleMessages, &kb.toggle_messages),
+            (Action::ToggleLog, &kb.toggle_log),
+        ];
+
+        let mut bindings = Vec::new();
+        for (action, combo) in configured.iter() {
+            // empty bindings are disabled
+            if combo.trim().is_empty() {
+                continue;
+            }
+            match combo.parse() {
+                Ok(combo) => bindings.push((*action, combo)),
+                Err(e) => tracing::warn!("Invalid keybinding for {:?}: {}", action, e),
+            }
+        }
+
+        Shortcuts { bindings }
+    }
+
+    /// Actions whose keys were pressed this frame
+    pub fn pressed(&self, ui: &imgui::Ui) -> Vec<Action> {
+        let io = ui.io();
+        if io.want_text_input {
+            return Vec::new();
+        }
+
+        self.bindings
+            .iter()
+            .filter(|(_, combo)| {
+                combo.ctrl == io.key_ctrl
+                    && combo.shift == io.key_shift
+                    && combo.alt == io.key_alt
+                    && ui.is_key_pressed(combo.key as u32)
+            })
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    /// Text for menu items, empty if the action is unbound
+    pub fn label(&self, action: Action) -> ImString {
+        match self.bindings.iter().find(|(a, _)| *a == action) {
+            Some((_, combo)) => ImString::new(combo.to_string()),
+            None => ImString::default(),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use derivative::Derivative;
+use imgui::im_str;
+
+use crate::audio::{overview::Overview, source::AudioSource};
+use crate::state::{Framerate, PlaybackState};
+use crate::ui::{format_time, ExternalEvents};
+
+const LANE_HEIGHT: f32 = 60.0;
+
+const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+const WAVEFORM_COLOR: [f32; 4] = [0.4, 0.7, 1.0, 1.0];
+const LABEL_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.6];
+const PLAYHEAD_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+const LOOP_COLOR: [f32; 4] = [1.0, 0.9, 0.3, 0.15];
+const LOOP_MARKER_COLOR: [f32; 4] = [1.0, 0.9, 0.3, 0.8];
+
+// what the timeline needs to know about a loaded source
+struct LaneSource<'a> {
+    name: String,
+    is_master: bool,
+    overview: &'a Overview,
+    sample_rate: f32,
+    start_frame: i64,
+    timeline_start: i64,
+    timeline_end: i64,
+}
+
+impl<'a> LaneSource<'a> {
+    fn end_secs(&self) -> f32 {
+        self.timeline_end as f32 / self.sample_rate
+    }
+
+    // peaks between two points on the timeline (secs)
+    fn peaks(&self, from: f32, to: f32) -> Option<(f32, f32)> {
+        let start = ((from * self.sample_rate) as i64).max(self.timeline_start);
+        let end = ((to * self.sample_rate) as i64).min(self.timeline_end);
+        if end <= start {
+            return None;
+        }
+
+        let to_file = self.start_frame - self.timeline_start;
+        self.overview.range(start + to_file, end + to_file)
+    }
+}
+
+/// Options of the timeline window
+#[derive(Derivative)]
+#[derivative(Default)]
+pub struct Timeline {
+    /// draw every source in its own lane instead of only the master
+    per_source: bool,
+    /// play master audio while dragging the playhead
+    #[derivative(Default(value = "true"))]
+    scrub: bool,
+}
+
+impl Timeline {
+    pub fn window(
+        &mut self,
+        ui: &imgui::Ui,
+        opened: &mut bool,
+        sources: &mut [AudioSource],
+        playback: &mut PlaybackState,
+        framerate: Framerate,
+        ext_events: &mut ExternalEvents,
+    ) {
+        let lane_sources = sources
+            .iter_mut()
+            .filter_map(|s| {
+                s.request_overview();
+                s.as_loaded()
+            })
+            .filter_map(|s| {
+                Some(LaneSource {
+                    name: s.path().file_name()?.to_string_lossy().into_owned(),
+                    is_master: s.connections.iter().any(|c| c.target.is_master()),
+                    overview: s.overview?,
+                    sample_rate: s.spec().sample_rate as f32,
+                    start_frame: i64::from(s.start_frame()),
+                    timeline_start: s.timeline_start(),
+                    timeline_end: s.timeline_end(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let lanes = if self.per_source {
+            lane_sources
+                .iter()
+                .map(|s| (s.name.as_str(), vec![s]))
+                .collect::<Vec<_>>()
+        } else {
+            // fall back to all sources if nothing is connected to master
+            let mut master = lane_sources
+                .iter()
+                .filter(|s| s.is_master)
+                .collect::<Vec<_>>();
+            if master.is_empty() {
+                master = lane_sources.iter().collect();
+            }
+            vec![("master", master)]
+        };
+
+        let duration = lane_sources
+            .iter()
+            .map(|s| s.end_secs())
+            .fold(0f32, f32::max)
+            .max(1.0);
+        let total_frames = framerate.secs_to_frame(f64::from(duration)) as u32;
+
+        playback.scrubbing = false;
+
+        let display_height = ui.io().display_size[1];
+        imgui::Window::new(im_str!("Timeline"))
+            .position(
+                [10.0, display_height - 10.0],
+                imgui::Condition::FirstUseEver,
+            )
+            .position_pivot([0.0, 1.0])
+            .size([800.0, 160.0], imgui::Condition::FirstUseEver)
+            .opened(opened)
+            .build(ui, || {
+                let secs = framerate.frame_to_secs(playback.position()) as f32;
+                ui.text(im_str!("{} / {}", format_time(secs), format_time(duration)));
+                ui.same_line(0.0);
+                ui.text(im_str!("Frame {} / {}", playback.frame, total_frames));
+                ui.same_line(0.0);
+                ui.checkbox(im_str!("Per Source"), &mut self.per_source);
+                ui.same_line(0.0);
+                ui.checkbox(im_str!("Scrub"), &mut self.scrub);
+
+                ui.checkbox(im_str!("Loop"), &mut playback.looping);
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Set In")) {
+                    playback.loop_in = Some(playback.frame);
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Set Out")) {
+                    playback.loop_out = Some(playback.frame);
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("Clear")) {
+                    playback.loop_in = None;
+                    playback.loop_out = None;
+                }
+                if playback.loop_in.is_some() || playback.loop_out.is_some() {
+                    ui.same_line(0.0);
+                    let marker_secs = |m: Option<u32>, default: f32| {
+                        m.map(|f| framerate.frame_to_secs(f64::from(f)) as f32)
+                            .unwrap_or(default)
+                    };
+                    ui.text(im_str!(
+                        "{} - {}",
+                        format_time(marker_secs(playback.loop_in, 0.0)),
+                        format_time(marker_secs(playback.loop_out, duration))
+                    ));
+                }
+
+                let origin = ui.cursor_screen_pos();
+                let width = ui.content_region_avail()[0].max(1.0);
+                let height = LANE_HEIGHT * lanes.len().max(1) as f32;
+                ui.invisible_button(im_str!("timeline"), [width, height]);
+
+                // click or drag to seek
+                if ui.is_item_active() {
+                    let x = (ui.io().mouse_pos[0] - origin[0]).max(0.0).min(width);
+                    let frame = (x / width * total_frames as f32) as u32;
+                    if frame != playback.frame {
+                        playback.seek(frame);
+                        *ext_events |= ExternalEvents::REDRAW_SCOPES;
+                    }
+                    playback.scrubbing = self.scrub && !playback.playing;
+                }
+
+                let draw_list = ui.get_window_draw_list();
+                draw_list
+                    .add_rect(
+                        origin,
+                        [origin[0] + width, origin[1] + height],
+                        BACKGROUND_COLOR,
+                    )
+                    .filled(true)
+                    .build();
+
+                for (i, (name, lane)) in lanes.iter().enumerate() {
+                    let top = origin[1] + i as f32 * LANE_HEIGHT;
+                    let center = top + LANE_HEIGHT / 2.0;
+                    let scale = LANE_HEIGHT / 2.0 - 2.0;
+
+                    for column in 0..width as u32 {
+                        let from = column as f32 / width * duration;
+                        let to = (column + 1) as f32 / width * duration;
+                        let peaks = lane.iter().filter_map(|s| s.peaks(from, to)).fold(
+                            None,
+                            |acc: Option<(f32, f32)>, (lo, hi)| match acc {
+                                Some((min, max)) => Some((min.min(lo), max.max(hi))),
+                                None => Some((lo, hi)),
+                            },
+                        );
+
+                        if let Some((min, max)) = peaks {
+                            let x = origin[0] + column as f32 + 0.5;
+                            draw_list
+                                .add_line(
+                                    [x, center - max.min(1.0) * scale],
+                                    [x, center - min.max(-1.0) * scale + 1.0],
+                                    WAVEFORM_COLOR,
+                                )
+                                .build();
+                        }
+                    }
+
+                    draw_list.add_text([origin[0] + 4.0, top + 2.0], LABEL_COLOR, name);
+                }
+
+                // loop region
+                if playback.loop_in.is_some() || playback.loop_out.is_some() {
+                    let frame_x = |frame: u32| {
+                        origin[0] + (frame as f32 / total_frames as f32).min(1.0) * width
+                    };
+                    let start = playback.loop_in.map(frame_x).unwrap_or(origin[0]);
+                    let end = playback.loop_out.map(frame_x).unwrap_or(origin[0] + width);
+                    if playback.looping {
+                        draw_list
+                            .add_rect([start, origin[1]], [end, origin[1] + height], LOOP_COLOR)
+                            .filled(true)
+                            .build();
+                    }
+                    for frame in [playback.loop_in, playback.loop_out].iter().flatten() {
+                        let x = frame_x(*frame);
+                        draw_list
+                            .add_line([x, origin[1]], [x, origin[1] + height], LOOP_MARKER_COLOR)
+                            .build();
+                    }
+                }
+
+                let x = origin[0] + (secs / duration).min(1.0) * width;
+                draw_list
+                    .add_line([x, origin[1]], [x, origin[1] + height], PLAYHEAD_COLOR)
+                    .thickness(2.0)
+                    .build();
+            });
+    }
+}